@@ -0,0 +1,146 @@
+//! Reading members of zip and tar archives (`assets/starter.zip!/src/main.rs`)
+
+use anyhow::{Context, Result, anyhow};
+use std::io::{Cursor, Read};
+use std::path::{Component, Path};
+
+/// Separates the archive path from the member path in a URI
+pub const MEMBER_SEPARATOR: &str = "!/";
+
+/// Check that a member path stays inside the archive
+/// The same rules as for repository paths apply: no `..`, nothing absolute.
+fn validate_member(member: &str) -> Result<()> {
+    if member.is_empty() {
+        return Err(anyhow!("Archive member path is empty"));
+    }
+    let escapes = Path::new(member)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes || member.contains('\\') {
+        return Err(anyhow!(
+            "Archive member path escapes the archive: {}",
+            member
+        ));
+    }
+    Ok(())
+}
+
+/// Normalize an entry name from the archive for comparison (`./a/b` → `a/b`)
+fn normalize(name: &str) -> String {
+    name.trim_start_matches("./").to_string()
+}
+
+/// Read one member from a zip, tar, or gzip-compressed tar archive
+/// The format is chosen by the archive's file extension.
+pub fn read_member(bytes: &[u8], archive_path: &str, member: &str) -> Result<Vec<u8>> {
+    validate_member(member)?;
+    let member = normalize(member);
+
+    let data = if archive_path.ends_with(".zip") {
+        read_zip_member(bytes, &member)?
+    } else if archive_path.ends_with(".tar.gz") || archive_path.ends_with(".tgz") {
+        let decoder = flate2::read::GzDecoder::new(Cursor::new(bytes));
+        read_tar_member(decoder, &member)?
+    } else if archive_path.ends_with(".tar") {
+        read_tar_member(Cursor::new(bytes), &member)?
+    } else {
+        return Err(anyhow!(
+            "Unsupported archive type (expected .zip, .tar, .tar.gz or .tgz): {}",
+            archive_path
+        ));
+    };
+
+    data.ok_or_else(|| anyhow!("No member '{}' in {}", member, archive_path))
+}
+
+fn read_zip_member(bytes: &[u8], member: &str) -> Result<Option<Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).context("Invalid zip archive")?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_file() && normalize(file.name()) == member {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            return Ok(Some(data));
+        }
+    }
+    Ok(None)
+}
+
+fn read_tar_member(reader: impl Read, member: &str) -> Result<Option<Vec<u8>>> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().context("Invalid tar archive")? {
+        let mut entry = entry.context("Invalid tar archive")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        if normalize(&name) == member {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            return Ok(Some(data));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn tar_gz_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_zip_member() {
+        let bytes = zip_bytes(&[("src/main.rs", b"fn main() {}\n")]);
+        let data = read_member(&bytes, "starter.zip", "src/main.rs").unwrap();
+        assert_eq!(data, b"fn main() {}\n");
+        assert!(read_member(&bytes, "starter.zip", "src/lib.rs").is_err());
+    }
+
+    #[test]
+    fn test_tar_gz_member() {
+        let bytes = tar_gz_bytes(&[("./README.md", b"# Starter\n")]);
+        let data = read_member(&bytes, "starter.tar.gz", "README.md").unwrap();
+        assert_eq!(data, b"# Starter\n");
+    }
+
+    #[test]
+    fn test_member_cannot_escape() {
+        let bytes = zip_bytes(&[("a.txt", b"a")]);
+        assert!(read_member(&bytes, "x.zip", "../a.txt").is_err());
+        assert!(read_member(&bytes, "x.zip", "/etc/passwd").is_err());
+        assert!(read_member(&bytes, "x.zip", "").is_err());
+        assert!(read_member(&bytes, "x.rar", "a.txt").is_err());
+        assert!(read_member(&bytes, "x.zip", "docs/../../a.txt").is_err());
+    }
+
+    #[test]
+    fn test_member_name_with_dots() {
+        let bytes = zip_bytes(&[("notes/v1..2.txt", b"v1 to v2\n")]);
+        let data = read_member(&bytes, "x.zip", "notes/v1..2.txt").unwrap();
+        assert_eq!(data, b"v1 to v2\n");
+    }
+}
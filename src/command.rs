@@ -0,0 +1,309 @@
+//! Command-output sources (`cmd:` references)
+//!
+//! Commands only run if they appear verbatim in the `[cmd]` allowlist in
+//! `.liaison.toml`. They run without a shell, in a scrubbed environment,
+//! with a timeout and a cap on how much output is kept.
+
+use anyhow::{Context, Result, anyhow};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::CmdConfig;
+
+/// Split a command line into arguments
+/// Supports single quotes (literal), double quotes (with `\"` and `\\` escapes)
+/// and backslash escapes outside quotes. No expansion of any kind is done.
+pub fn split_args(command: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(anyhow!("Unclosed ' in command: {}", command)),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err(anyhow!("Unclosed \" in command: {}", command)),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(anyhow!("Unclosed \" in command: {}", command)),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                match chars.next() {
+                    Some(c) => current.push(c),
+                    None => return Err(anyhow!("Trailing \\ in command: {}", command)),
+                }
+            }
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
+}
+
+/// Run an allowlisted command and return its stdout
+pub fn run(command: &str, config: &CmdConfig, repo_root: &Path) -> Result<String> {
+    let args = split_args(command)?;
+    if args.is_empty() {
+        return Err(anyhow!("cmd: needs a command"));
+    }
+
+    // Compare argument lists so quoting and spacing differences don't matter
+    let mut allowed = false;
+    for entry in &config.allow {
+        if split_args(entry)? == args {
+            allowed = true;
+            break;
+        }
+    }
+    if !allowed {
+        return Err(anyhow!(
+            "Command is not allowlisted in [cmd] allow in .liaison.toml: {}",
+            command
+        ));
+    }
+
+    let cwd = match &config.cwd {
+        Some(dir) => repo_root.join(dir),
+        None => repo_root.to_path_buf(),
+    };
+
+    let mut child = Command::new(&args[0])
+        .args(&args[1..])
+        .current_dir(&cwd)
+        .env_clear()
+        .envs(
+            config
+                .env
+                .iter()
+                .filter_map(|name| std::env::var_os(name).map(|value| (name, value))),
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run command: {}", command))?;
+
+    // Read both pipes on threads so a chatty child can't block on a full pipe;
+    // reading stops one byte past the limit so overflow is detectable
+    let limit = config.max_output as u64 + 1;
+    let mut stdout = child.stdout.take().unwrap().take(limit);
+    let mut stderr = child.stderr.take().unwrap().take(limit);
+    let overflowed = Arc::new(AtomicBool::new(false));
+    let overflow_flag = overflowed.clone();
+    let max_output = config.max_output;
+    let (stdout_sender, stdout_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let result = stdout.read_to_end(&mut buf).map(|_| {
+            overflow_flag.store(buf.len() > max_output, Ordering::Relaxed);
+            buf
+        });
+        let _ = stdout_sender.send(result);
+    });
+    let (stderr_sender, stderr_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr_sender.send(stderr.read_to_end(&mut buf).map(|_| buf));
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(config.timeout);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if overflowed.load(Ordering::Relaxed) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!(
+                "Command output exceeded {} bytes: {}",
+                config.max_output,
+                command
+            ));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!(
+                "Command timed out after {}s: {}",
+                config.timeout,
+                command
+            ));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // A background process the command left behind can hold the pipes open after
+    // the command itself exits, so collecting the output shares the deadline
+    let collect = |receiver: &mpsc::Receiver<std::io::Result<Vec<u8>>>| match receiver
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(anyhow!(
+            "Command timed out after {}s waiting for its output to close: {}",
+            config.timeout,
+            command
+        )),
+    };
+    let stdout = collect(&stdout_receiver)?;
+    let stderr = collect(&stderr_receiver)?;
+
+    if stdout.len() > config.max_output {
+        return Err(anyhow!(
+            "Command output exceeded {} bytes: {}",
+            config.max_output,
+            command
+        ));
+    }
+
+    let expected = config.exit_codes.contains(&status.code().unwrap_or(-1));
+    if !expected {
+        return Err(anyhow!(
+            "Command failed ({}): {}\n{}",
+            status,
+            command,
+            String::from_utf8_lossy(&stderr).trim_end()
+        ));
+    }
+
+    String::from_utf8(stdout).with_context(|| format!("Command output is not UTF-8: {}", command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(allow: &[&str]) -> CmdConfig {
+        CmdConfig {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            ..CmdConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"echo 'a  b' "c \"d\"" e\ f"#).unwrap(),
+            vec!["echo", "a  b", "c \"d\"", "e f"]
+        );
+        assert_eq!(split_args("  ls   -la ").unwrap(), vec!["ls", "-la"]);
+        assert_eq!(split_args("echo ''").unwrap(), vec!["echo", ""]);
+        assert!(split_args("echo 'oops").is_err());
+    }
+
+    #[test]
+    fn test_requires_allowlist() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let err = run("echo hi", &CmdConfig::default(), root).unwrap_err();
+        assert!(err.to_string().contains("not allowlisted"));
+
+        // Arguments must match exactly, not just the program
+        let err = run("echo bye", &config(&["echo hi"]), root).unwrap_err();
+        assert!(err.to_string().contains("not allowlisted"));
+
+        assert_eq!(
+            run("echo  'hi'", &config(&["echo hi"]), root).unwrap(),
+            "hi\n"
+        );
+    }
+
+    #[test]
+    fn test_scrubs_environment() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let cmd = CmdConfig {
+            env: vec!["PATH".to_string()],
+            ..config(&["env"])
+        };
+        let output = run("env", &cmd, root).unwrap();
+        let names: Vec<&str> = output
+            .lines()
+            .filter_map(|l| l.split_once('=').map(|(k, _)| k))
+            .collect();
+        assert_eq!(names, vec!["PATH"]);
+    }
+
+    #[test]
+    fn test_exit_code_checked() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let err = run("false", &config(&["false"]), root).unwrap_err();
+        assert!(err.to_string().contains("Command failed"));
+
+        let cmd = CmdConfig {
+            exit_codes: vec![1],
+            ..config(&["false"])
+        };
+        assert_eq!(run("false", &cmd, root).unwrap(), "");
+    }
+
+    #[test]
+    fn test_limits() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let cmd = CmdConfig {
+            max_output: 100,
+            ..config(&["yes"])
+        };
+        let err = run("yes", &cmd, root).unwrap_err();
+        assert!(err.to_string().contains("exceeded 100 bytes"));
+
+        let cmd = CmdConfig {
+            timeout: 1,
+            ..config(&["sleep 10"])
+        };
+        let err = run("sleep 10", &cmd, root).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+
+    #[test]
+    fn test_timeout_covers_background_processes() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let command = "sh -c 'sleep 5 &'";
+        let cmd = CmdConfig {
+            timeout: 1,
+            env: vec!["PATH".to_string()],
+            ..config(&[command])
+        };
+        let started = Instant::now();
+        let err = run(command, &cmd, root).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(4));
+    }
+}
@@ -1,14 +1,334 @@
 use anyhow::Result;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::Path;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct Config {
     #[serde(default)]
     pub glob: GlobConfig,
+
+    #[serde(default)]
+    pub sanitize: SanitizeConfig,
+
+    #[serde(default)]
+    pub permalink: PermalinkConfig,
+
+    #[serde(default)]
+    pub cmd: CmdConfig,
+
+    #[serde(default)]
+    pub env: EnvConfig,
+
+    #[serde(default)]
+    pub sql: SqlConfig,
+
+    #[serde(default)]
+    pub cache: CacheConfig,
+
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    #[serde(default)]
+    pub paths: PathsConfig,
 }
 
-#[derive(Debug, Deserialize)]
+/// How local paths are confined to the repository
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PathsConfig {
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+}
+
+/// Which symlinks local paths may go through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    /// Refuse any path that goes through a symlink
+    Never,
+    /// Follow symlinks whose target is inside the repository
+    #[default]
+    WithinRepo,
+    /// Follow symlinks wherever they point
+    Always,
+}
+
+/// Which remote sources may be fetched
+#[derive(Debug, Clone, Deserialize)]
+pub struct NetworkConfig {
+    /// Hosts (`example.com`, `*.example.com`) or URL prefixes (`https://example.com/docs/`);
+    /// when absent, any host is allowed
+    pub allow: Option<Vec<String>>,
+
+    /// Allow plain `http://` URLs
+    #[serde(default = "default_true")]
+    pub allow_http: bool,
+
+    /// Allow remote content to contain transclusions of its own
+    #[serde(default = "default_true")]
+    pub nested: bool,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            allow: None,
+            allow_http: true,
+            nested: true,
+        }
+    }
+}
+
+/// Settings for remote fetches; `hosts` rules override them per host
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+
+    /// Seconds to wait for a connection
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: u64,
+
+    /// Seconds to wait for the whole response
+    #[serde(default = "default_http_timeout")]
+    pub timeout: u64,
+
+    /// Extra attempts after a connection failure, timeout, 429 or 5xx
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+
+    /// Milliseconds before the first retry; doubled for each one after
+    #[serde(default = "default_backoff_ms")]
+    pub backoff_ms: u64,
+
+    /// Proxy URL for every request, or "none" to ignore proxy environment variables
+    pub proxy: Option<String>,
+
+    /// Maximum bytes of a response body
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+
+    /// Read credentials for hosts without their own auth from `~/.netrc`
+    #[serde(default)]
+    pub netrc: bool,
+
+    /// Headers sent with every request; `${VAR}` expands environment variables
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Remote sources fetched at once while prefetching
+    #[serde(default = "default_parallelism")]
+    pub parallelism: usize,
+
+    /// Most requests per second to any one host
+    pub rate_limit: Option<f64>,
+
+    /// Rules keyed by host name; `*.example.com` matches subdomains
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostConfig>,
+}
+
+/// Per-host overrides and credentials
+/// Credentials and headers are only ever sent to the host they're configured for;
+/// a redirect to another host is requested with that host's own settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HostConfig {
+    /// Environment variable holding a bearer token
+    pub bearer_env: Option<String>,
+
+    /// Environment variables holding basic auth credentials
+    pub basic_user_env: Option<String>,
+    pub basic_password_env: Option<String>,
+
+    pub netrc: Option<bool>,
+
+    /// Added to (and overriding) the global headers
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    pub connect_timeout: Option<u64>,
+    pub timeout: Option<u64>,
+    pub retries: Option<u32>,
+    pub backoff_ms: Option<u64>,
+    pub proxy: Option<String>,
+    pub max_size: Option<u64>,
+    pub rate_limit: Option<f64>,
+}
+
+fn default_user_agent() -> String {
+    format!("liaison/{}", env!("CARGO_PKG_VERSION"))
+}
+
+fn default_connect_timeout() -> u64 {
+    10
+}
+
+fn default_http_timeout() -> u64 {
+    30
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_parallelism() -> usize {
+    8
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            user_agent: default_user_agent(),
+            connect_timeout: default_connect_timeout(),
+            timeout: default_http_timeout(),
+            retries: default_retries(),
+            backoff_ms: default_backoff_ms(),
+            proxy: None,
+            max_size: default_max_size(),
+            netrc: false,
+            headers: BTreeMap::new(),
+            parallelism: default_parallelism(),
+            rate_limit: None,
+            hosts: BTreeMap::new(),
+        }
+    }
+}
+
+/// How remote sources use the on-disk cache; set by `--offline` and `--refresh`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FetchMode {
+    /// Use fresh entries, revalidate stale ones
+    #[default]
+    Normal,
+    /// Never touch the network; every remote source must be cached
+    Offline,
+    /// Download everything again, ignoring cached entries
+    Refresh,
+}
+
+/// On-disk cache of remote sources
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheConfig {
+    /// Cache directory, relative to the repository root
+    #[serde(default = "default_cache_dir")]
+    pub dir: String,
+
+    /// Seconds a cached response is used without asking the server again
+    #[serde(default)]
+    pub ttl: u64,
+
+    /// Per-host TTLs, overriding `ttl`
+    #[serde(default)]
+    pub hosts: BTreeMap<String, u64>,
+
+    #[serde(skip)]
+    pub mode: FetchMode,
+}
+
+fn default_cache_dir() -> String {
+    ".liaison/cache".to_string()
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_cache_dir(),
+            ttl: 0,
+            hosts: BTreeMap::new(),
+            mode: FetchMode::default(),
+        }
+    }
+}
+
+/// Named queries for SQLite sources (`data/errors.sqlite?query=errors`)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SqlConfig {
+    #[serde(default)]
+    pub queries: BTreeMap<String, String>,
+}
+
+/// Environment variables that `env:` references may read
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EnvConfig {
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// Commands that `cmd:` references may run; nothing runs unless listed here
+#[derive(Debug, Clone, Deserialize)]
+pub struct CmdConfig {
+    /// Exact command lines that are allowed to run
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// Working directory, relative to the repository root
+    pub cwd: Option<String>,
+
+    /// Seconds before a command is killed
+    #[serde(default = "default_cmd_timeout")]
+    pub timeout: u64,
+
+    /// Maximum bytes of stdout kept; larger output is an error
+    #[serde(default = "default_cmd_max_output")]
+    pub max_output: usize,
+
+    /// Environment variables passed through; all others are removed
+    #[serde(default = "default_cmd_env")]
+    pub env: Vec<String>,
+
+    /// Exit codes that count as success
+    #[serde(default = "default_cmd_exit_codes")]
+    pub exit_codes: Vec<i32>,
+}
+
+fn default_cmd_timeout() -> u64 {
+    30
+}
+
+fn default_cmd_max_output() -> usize {
+    1024 * 1024
+}
+
+fn default_cmd_env() -> Vec<String> {
+    vec!["PATH".to_string(), "HOME".to_string()]
+}
+
+fn default_cmd_exit_codes() -> Vec<i32> {
+    vec![0]
+}
+
+impl Default for CmdConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            cwd: None,
+            timeout: default_cmd_timeout(),
+            max_output: default_cmd_max_output(),
+            env: default_cmd_env(),
+            exit_codes: default_cmd_exit_codes(),
+        }
+    }
+}
+
+/// Source links appended by `?permalink`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PermalinkConfig {
+    /// URL template with `{commit}`, `{path}`, `{start}` and `{end}` placeholders
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct GlobConfig {
     #[serde(default)]
     pub include: Vec<String>,
@@ -30,14 +350,117 @@ impl Default for GlobConfig {
     }
 }
 
+/// Allowlist-based sanitization of transcluded HTML
+#[derive(Debug, Clone, Deserialize)]
+pub struct SanitizeConfig {
+    /// Sanitize HTML and Markdown fetched from http(s) sources
+    #[serde(default = "default_true")]
+    pub remote: bool,
+
+    /// Sanitize HTML and Markdown read from the repository as well
+    #[serde(default)]
+    pub local: bool,
+
+    /// Tags that are kept; other tags are unwrapped (or dropped with their content
+    /// for scripts, styles and embeds)
+    #[serde(default = "default_allowed_tags")]
+    pub tags: Vec<String>,
+
+    /// Attributes that are kept on allowed tags; `on*` handlers are always removed
+    #[serde(default = "default_allowed_attributes")]
+    pub attributes: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_allowed_tags() -> Vec<String> {
+    [
+        "a",
+        "abbr",
+        "b",
+        "blockquote",
+        "br",
+        "caption",
+        "code",
+        "dd",
+        "del",
+        "details",
+        "div",
+        "dl",
+        "dt",
+        "em",
+        "figcaption",
+        "figure",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "hr",
+        "i",
+        "img",
+        "ins",
+        "kbd",
+        "li",
+        "mark",
+        "ol",
+        "p",
+        "pre",
+        "q",
+        "s",
+        "samp",
+        "section",
+        "small",
+        "span",
+        "strong",
+        "sub",
+        "summary",
+        "sup",
+        "table",
+        "tbody",
+        "td",
+        "tfoot",
+        "th",
+        "thead",
+        "tr",
+        "u",
+        "ul",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+fn default_allowed_attributes() -> Vec<String> {
+    [
+        "alt", "class", "colspan", "height", "href", "id", "lang", "rowspan", "src", "start",
+        "title", "width",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+impl Default for SanitizeConfig {
+    fn default() -> Self {
+        Self {
+            remote: true,
+            local: false,
+            tags: default_allowed_tags(),
+            attributes: default_allowed_attributes(),
+        }
+    }
+}
+
 impl Config {
     pub fn load(repo_root: &Path) -> Result<Self> {
         let config_path = repo_root.join(".liaison.toml");
 
         if !config_path.exists() {
-            return Ok(Config {
-                glob: GlobConfig::default(),
-            });
+            return Ok(Config::default());
         }
 
         let content = std::fs::read_to_string(&config_path)?;
@@ -0,0 +1,245 @@
+//! Sources from crates available locally (`crate:serde@1.0.210/src/de/mod.rs`)
+//!
+//! Crates are looked up in the workspace's `cargo metadata` (workspace
+//! members, path and vendored dependencies) and then in the Cargo registry
+//! source cache. Nothing is downloaded.
+
+use anyhow::{Result, anyhow};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// A package known to `cargo metadata`
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub dir: PathBuf,
+}
+
+/// Split `name[@version]/path` into its parts
+pub fn parse_spec(spec: &str) -> Result<(&str, Option<&str>, &str)> {
+    let (package, path) = spec
+        .split_once('/')
+        .ok_or_else(|| anyhow!("crate: needs a file path, e.g. crate:serde@1.0.210/src/lib.rs"))?;
+    let outside = Path::new(path)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)));
+    if path.is_empty() || outside {
+        return Err(anyhow!("Invalid path in crate: reference: {}", spec));
+    }
+    match package.split_once('@') {
+        Some((name, version)) if !name.is_empty() && !version.is_empty() => {
+            Ok((name, Some(version), path))
+        }
+        None if !package.is_empty() => Ok((package, None, path)),
+        _ => Err(anyhow!("Invalid crate name in crate: reference: {}", spec)),
+    }
+}
+
+/// The real location of `path` inside the crate at `dir`
+/// Symlinks are followed, but the file they lead to must still be inside the crate.
+pub fn confine(dir: &Path, path: &str) -> Result<PathBuf> {
+    let root = dir
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to read crate directory {}: {}", dir.display(), e))?;
+    let file = root
+        .join(path)
+        .canonicalize()
+        .map_err(|e| anyhow!("Failed to read {} from {}: {}", path, dir.display(), e))?;
+    if !file.starts_with(&root) {
+        return Err(anyhow!(
+            "Path {} escapes the crate directory {}",
+            path,
+            dir.display()
+        ));
+    }
+    Ok(file)
+}
+
+/// The Cargo home directory (`$CARGO_HOME`, or `~/.cargo`)
+pub fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+}
+
+/// Packages of the workspace and its resolved dependencies, read offline
+/// Returns an empty list if the repository isn't a Cargo workspace.
+pub fn metadata_packages(repo_root: &Path) -> Vec<Package> {
+    let output = Command::new("cargo")
+        .args(["metadata", "--offline", "--format-version", "1"])
+        .current_dir(repo_root)
+        .output();
+    let Ok(output) = output else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+    let Ok(metadata) = serde_json::from_slice::<serde_json::Value>(&output.stdout) else {
+        return Vec::new();
+    };
+
+    metadata["packages"]
+        .as_array()
+        .map(|packages| {
+            packages
+                .iter()
+                .filter_map(|p| {
+                    Some(Package {
+                        name: p["name"].as_str()?.to_string(),
+                        version: p["version"].as_str()?.to_string(),
+                        dir: Path::new(p["manifest_path"].as_str()?)
+                            .parent()?
+                            .to_path_buf(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Find the source directory of a crate
+pub fn locate(
+    name: &str,
+    version: Option<&str>,
+    packages: &[Package],
+    cargo_home: Option<&Path>,
+) -> Result<PathBuf> {
+    let mut matches: Vec<&Package> = packages
+        .iter()
+        .filter(|p| p.name == name && version.is_none_or(|v| p.version == v))
+        .collect();
+    matches.dedup_by(|a, b| a.version == b.version);
+    match matches.as_slice() {
+        [package] => return Ok(package.dir.clone()),
+        [] => {}
+        _ => {
+            let versions: Vec<&str> = matches.iter().map(|p| p.version.as_str()).collect();
+            return Err(anyhow!(
+                "Several versions of crate {} are in use ({}); add @version",
+                name,
+                versions.join(", ")
+            ));
+        }
+    }
+
+    let Some(version) = version else {
+        return Err(anyhow!(
+            "Crate {} is not part of this workspace's dependencies; add @version to read it from the registry cache",
+            name
+        ));
+    };
+
+    let dir_name = format!("{}-{}", name, version);
+    if let Some(home) = cargo_home
+        && let Ok(registries) = std::fs::read_dir(home.join("registry/src"))
+    {
+        let mut candidates: Vec<PathBuf> = registries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join(&dir_name))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        candidates.sort();
+        if let Some(dir) = candidates.into_iter().next() {
+            return Ok(dir);
+        }
+    }
+
+    Err(anyhow!(
+        "Crate {} {} is not available locally (not in cargo metadata or the registry cache); run `cargo fetch` with it as a dependency",
+        name,
+        version
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        assert_eq!(
+            parse_spec("serde@1.0.210/src/de/mod.rs").unwrap(),
+            ("serde", Some("1.0.210"), "src/de/mod.rs")
+        );
+        assert_eq!(
+            parse_spec("core/src/lib.rs").unwrap(),
+            ("core", None, "src/lib.rs")
+        );
+        assert!(parse_spec("serde@1.0.210").is_err());
+        assert!(parse_spec("serde/../../etc/passwd").is_err());
+        assert!(parse_spec("serde//tmp/outside-secret.txt").is_err());
+        assert!(parse_spec("serde/./src/lib.rs").is_err());
+        assert_eq!(
+            parse_spec("serde/CHANGELOG..old.md").unwrap(),
+            ("serde", None, "CHANGELOG..old.md")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_confine() {
+        let base = std::env::temp_dir().join(format!("liaison-crate-{}", std::process::id()));
+        let crate_dir = base.join("serde-1.0.210");
+        std::fs::create_dir_all(crate_dir.join("src")).unwrap();
+        std::fs::write(crate_dir.join("src/lib.rs"), "// lib").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink("../../secret.txt", crate_dir.join("src/leak.rs")).unwrap();
+
+        let lib = confine(&crate_dir, "src/lib.rs").unwrap();
+        assert_eq!(std::fs::read_to_string(lib).unwrap(), "// lib");
+        let err = confine(&crate_dir, "src/leak.rs").unwrap_err();
+        assert!(err.to_string().contains("escapes the crate directory"));
+        assert!(confine(&crate_dir, "src/missing.rs").is_err());
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_locate_in_registry_cache() {
+        let home = std::env::temp_dir().join(format!("liaison-cargo-{}", std::process::id()));
+        let crate_dir = home.join("registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.210");
+        std::fs::create_dir_all(&crate_dir).unwrap();
+
+        assert_eq!(
+            locate("serde", Some("1.0.210"), &[], Some(&home)).unwrap(),
+            crate_dir
+        );
+        let err = locate("serde", Some("1.0.999"), &[], Some(&home)).unwrap_err();
+        assert!(err.to_string().contains("not available locally"));
+        assert!(locate("serde", None, &[], Some(&home)).is_err());
+
+        std::fs::remove_dir_all(home).unwrap();
+    }
+
+    #[test]
+    fn test_locate_prefers_metadata() {
+        let packages = vec![
+            Package {
+                name: "core".to_string(),
+                version: "0.3.0".to_string(),
+                dir: PathBuf::from("/repo/crates/core"),
+            },
+            Package {
+                name: "rand".to_string(),
+                version: "0.8.5".to_string(),
+                dir: PathBuf::from("/reg/rand-0.8.5"),
+            },
+            Package {
+                name: "rand".to_string(),
+                version: "0.9.0".to_string(),
+                dir: PathBuf::from("/reg/rand-0.9.0"),
+            },
+        ];
+        assert_eq!(
+            locate("core", None, &packages, None).unwrap(),
+            PathBuf::from("/repo/crates/core")
+        );
+        assert!(locate("rand", None, &packages, None).is_err());
+        assert_eq!(
+            locate("rand", Some("0.9.0"), &packages, None).unwrap(),
+            PathBuf::from("/reg/rand-0.9.0")
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Unified diffs between two revisions or two files (`diff:` references)
+//!
+//! `diff:src/lib.rs#helper@v1..v2` compares one block at two revisions;
+//! `diff:old.rs#x..new.rs#x` compares two references. The diff is plain
+//! unified-diff text until it is rendered for the host.
+
+use anyhow::{Result, anyhow};
+use similar::TextDiff;
+
+use crate::resolver::Reference;
+
+/// Positions of the `..` that may separate the two sides
+/// A `..` standing as a path component (`../shared/a.rs`) never does.
+fn separators(spec: &str) -> Vec<usize> {
+    spec.match_indices("..")
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            let starts_component = i == 0 || spec[..i].ends_with('/');
+            let ends_component = spec[i + 2..].is_empty() || spec[i + 2..].starts_with('/');
+            !(starts_component && ends_component)
+        })
+        .collect()
+}
+
+/// Split a `diff:` spec into the old and new references
+/// The right-hand side is a bare revision unless it contains a `#` or `/`;
+/// an empty revision (`@v1..`) means the working tree. A revision is split off
+/// at the last `..`, so the path may contain `..` (`v1..2.txt@v1..v2`); two
+/// references are split at the first, so only the right one may (write
+/// `.%2E` in the left one).
+pub fn parse_spec(spec: &str) -> Result<(Reference, Reference)> {
+    let separators = separators(spec);
+    let (Some(&first), Some(&last)) = (separators.first(), separators.last()) else {
+        return Err(anyhow!("diff: needs two sides separated by '..': {}", spec));
+    };
+
+    let (left, right) = (&spec[..last], &spec[last + 2..]);
+    if !right.contains(['#', '/'])
+        && let Some((base, old_rev)) = left.rsplit_once('@')
+    {
+        if old_rev.is_empty() {
+            return Err(anyhow!("diff: is missing the old revision: {}", spec));
+        }
+        let mut old = Reference::parse(base)?;
+        old.rev = Some(old_rev.to_string());
+        let mut new = Reference::parse(base)?;
+        new.rev = (!right.is_empty()).then(|| right.to_string());
+        return Ok((old, new));
+    }
+
+    let (left, right) = (&spec[..first], &spec[first + 2..]);
+    if left.is_empty() || right.is_empty() {
+        return Err(anyhow!("diff: needs two references: {}", spec));
+    }
+    Ok((Reference::parse(left)?, Reference::parse(right)?))
+}
+
+/// Label for one side of the diff in the `---`/`+++` header
+pub fn label(reference: &Reference) -> String {
+    match &reference.selector {
+        Some(selector) => format!("{}#{}", reference.display_uri(), selector),
+        None => reference.display_uri(),
+    }
+}
+
+/// Unified diff of two texts with three lines of context
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    // Compare whole lines; a missing final newline is not a meaningful change here
+    let with_newline = |s: &str| {
+        if s.is_empty() || s.ends_with('\n') {
+            s.to_string()
+        } else {
+            format!("{}\n", s)
+        }
+    };
+    let (old, new) = (with_newline(old), with_newline(new));
+
+    TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(3)
+        .missing_newline_hint(false)
+        .header(old_label, new_label)
+        .to_string()
+        .trim_end_matches('\n')
+        .to_string()
+}
+
+/// Render as a fenced ```diff block for Markdown
+/// The fence is longer than any run of backticks in the diff, so none can close it.
+pub fn to_markdown(diff: &str) -> String {
+    let longest = diff.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}diff\n{}\n{}", fence, diff, fence)
+}
+
+/// Render as escaped HTML, one `<span>` per line classed by its role
+pub fn to_html(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            let class = if line.starts_with("---") || line.starts_with("+++") {
+                "diff-header"
+            } else if line.starts_with("@@") {
+                "diff-hunk"
+            } else if line.starts_with('+') {
+                "diff-add"
+            } else if line.starts_with('-') {
+                "diff-del"
+            } else {
+                "diff-context"
+            };
+            format!(
+                "<span class=\"{}\">{}</span>",
+                class,
+                crate::html::escape_html(line)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_revision_spec() {
+        let (old, new) = parse_spec("src/lib.rs#helper@v1..v2").unwrap();
+        assert_eq!(old.uri, "src/lib.rs");
+        assert_eq!(old.selector, Some("helper".to_string()));
+        assert_eq!(old.rev, Some("v1".to_string()));
+        assert_eq!(new.rev, Some("v2".to_string()));
+
+        let (_, new) = parse_spec("src/lib.rs@HEAD~1..").unwrap();
+        assert_eq!(new.rev, None);
+
+        let (old, new) = parse_spec("../shared/a.rs@v1..v2").unwrap();
+        assert_eq!(old.uri, "../shared/a.rs");
+        assert_eq!(
+            (old.rev.unwrap(), new.rev.unwrap()),
+            ("v1".into(), "v2".into())
+        );
+
+        let (old, new) = parse_spec("v1..2.txt@v1..").unwrap();
+        assert_eq!(old.uri, "v1..2.txt");
+        assert_eq!(old.rev, Some("v1".to_string()));
+        assert_eq!(new.rev, None);
+    }
+
+    #[test]
+    fn test_parse_file_spec() {
+        let (old, new) = parse_spec("old.rs#x..new.rs#x").unwrap();
+        assert_eq!(old.uri, "old.rs");
+        assert_eq!(new.uri, "new.rs");
+        assert_eq!(new.selector, Some("x".to_string()));
+        assert_eq!(label(&old), "old.rs#x");
+
+        assert!(parse_spec("old.rs").is_err());
+        assert!(parse_spec("../old.rs").is_err());
+
+        let (old, new) = parse_spec("../shared/a.rs#x..../shared/b.rs#x").unwrap();
+        assert_eq!(old.uri, "../shared/a.rs");
+        assert_eq!(new.uri, "../shared/b.rs");
+
+        let (old, new) = parse_spec("v1.%2E2.txt..v2..3.txt#x").unwrap();
+        assert_eq!(old.uri, "v1..2.txt");
+        assert_eq!(new.uri, "v2..3.txt");
+    }
+
+    #[test]
+    fn test_unified() {
+        let diff = unified("a\nb\nc", "a\nB\nc", "old", "new");
+        assert_eq!(diff, "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c");
+    }
+
+    #[test]
+    fn test_render() {
+        let diff = "--- a\n+++ b\n@@ -1 +1 @@\n-Vec<u8>\n+Vec<u16>";
+        assert_eq!(
+            to_html(diff),
+            "<span class=\"diff-header\">--- a</span>\n\
+             <span class=\"diff-header\">+++ b</span>\n\
+             <span class=\"diff-hunk\">@@ -1 +1 @@</span>\n\
+             <span class=\"diff-del\">-Vec&lt;u8&gt;</span>\n\
+             <span class=\"diff-add\">+Vec&lt;u16&gt;</span>"
+        );
+        assert_eq!(to_markdown("-a\n+b"), "```diff\n-a\n+b\n```");
+        assert_eq!(
+            to_markdown("-```rust\n+````rust"),
+            "`````diff\n-```rust\n+````rust\n`````"
+        );
+    }
+}
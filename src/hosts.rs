@@ -84,6 +84,7 @@ impl HostType for HtmlElementHost {
             reference: transclude.reference.clone(),
             attribute_name: "transclude".to_string(),
             tag_name: String::new(), // Unknown tag from TranscludeMatch
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
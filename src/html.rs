@@ -12,11 +12,12 @@ pub struct TranscludeBlock {
     pub attribute_name: String,
     /// The tag name of the element (e.g., "pre", "code", "div")
     pub tag_name: String,
+    /// The element's own `id`, used to tell apart sites sharing a reference
+    pub element_id: Option<String>,
     #[allow(dead_code)] // Kept for API compatibility
     pub element_html: String,
-    #[allow(dead_code)] // Kept for API compatibility
+    /// Byte range of the element's start tag in the source
     pub start_pos: usize,
-    #[allow(dead_code)] // Kept for API compatibility
     pub end_pos: usize,
 }
 
@@ -83,6 +84,8 @@ pub fn find_transclude_blocks(html: &str) -> Result<Vec<TranscludeBlock>> {
     let settings = RewriteStrSettings {
         element_content_handlers: vec![element!("*", move |el| {
             let tag_name = el.tag_name();
+            let element_id = el.get_attribute("id");
+            let start_tag = el.source_location().bytes();
 
             // Check for regular transclude attribute
             if let Some(reference) = el.get_attribute("transclude") {
@@ -90,9 +93,10 @@ pub fn find_transclude_blocks(html: &str) -> Result<Vec<TranscludeBlock>> {
                     reference,
                     attribute_name: "transclude".to_string(),
                     tag_name: tag_name.clone(),
+                    element_id: element_id.clone(),
                     element_html: String::new(),
-                    start_pos: 0,
-                    end_pos: 0,
+                    start_pos: start_tag.start,
+                    end_pos: start_tag.end,
                 });
             }
 
@@ -106,9 +110,10 @@ pub fn find_transclude_blocks(html: &str) -> Result<Vec<TranscludeBlock>> {
                         reference,
                         attribute_name: attr_name.clone(),
                         tag_name: tag_name.clone(),
+                        element_id: element_id.clone(),
                         element_html: String::new(),
-                        start_pos: 0,
-                        end_pos: 0,
+                        start_pos: start_tag.start,
+                        end_pos: start_tag.end,
                     });
                 }
             }
@@ -124,7 +129,7 @@ pub fn find_transclude_blocks(html: &str) -> Result<Vec<TranscludeBlock>> {
 }
 
 /// HTML-escape text content for safe inclusion in HTML
-fn escape_html(text: &str) -> String {
+pub fn escape_html(text: &str) -> String {
     text.replace('&', "&amp;")
         .replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -241,10 +246,13 @@ pub fn replace_inner_html(
     };
 
     let reference = block.reference.clone();
+    let element_id = block.element_id.clone();
     let content_clone = content.clone();
     let settings = RewriteStrSettings {
         element_content_handlers: vec![element!("*[transclude]", move |el| {
-            if el.get_attribute("transclude").as_deref() == Some(reference.as_str()) {
+            // Sites sharing a reference are told apart by their own id, if any
+            let same_site = element_id.is_none() || el.get_attribute("id") == element_id;
+            if el.get_attribute("transclude").as_deref() == Some(reference.as_str()) && same_site {
                 if el.is_self_closing() {
                     // Self-closing tags need to be replaced entirely
                     // Rebuild the opening tag with all attributes
@@ -275,6 +283,136 @@ pub fn replace_inner_html(
     Ok(rewrite_str(html, settings)?)
 }
 
+/// Attributes whose value is a single id or a space-separated list of ids
+const ID_REFERENCE_ATTRIBUTES: &[&str] = &[
+    "for",
+    "form",
+    "list",
+    "headers",
+    "aria-activedescendant",
+    "aria-controls",
+    "aria-describedby",
+    "aria-details",
+    "aria-errormessage",
+    "aria-flowto",
+    "aria-labelledby",
+    "aria-owns",
+];
+
+/// Attributes whose value is a `#id` fragment link
+const FRAGMENT_ATTRIBUTES: &[&str] = &["href", "xlink:href", "usemap"];
+
+/// Rename every `id` defined in the fragment, along with the internal references to it
+/// (`for`, `aria-*`, `headers`, `href="#..."` and friends). References to ids that are
+/// not defined inside the fragment are left untouched.
+pub fn rename_ids(html: &str, rename: impl Fn(&str) -> String) -> Result<String> {
+    let ids = Rc::new(RefCell::new(std::collections::HashSet::new()));
+    let ids_clone = ids.clone();
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("[id]", move |el| {
+                if let Some(id) = el.get_attribute("id") {
+                    ids_clone.borrow_mut().insert(id);
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )?;
+
+    let ids = Rc::try_unwrap(ids).unwrap().into_inner();
+    if ids.is_empty() {
+        return Ok(html.to_string());
+    }
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![element!("*", |el| {
+            if let Some(id) = el.get_attribute("id")
+                && ids.contains(&id)
+            {
+                el.set_attribute("id", &rename(&id))?;
+            }
+            for attr in ID_REFERENCE_ATTRIBUTES {
+                if let Some(value) = el.get_attribute(attr) {
+                    let renamed: Vec<String> = value
+                        .split_whitespace()
+                        .map(|token| {
+                            if ids.contains(token) {
+                                rename(token)
+                            } else {
+                                token.to_string()
+                            }
+                        })
+                        .collect();
+                    el.set_attribute(attr, &renamed.join(" "))?;
+                }
+            }
+            for attr in FRAGMENT_ATTRIBUTES {
+                if let Some(value) = el.get_attribute(attr)
+                    && let Some(target) = value.strip_prefix('#')
+                    && ids.contains(target)
+                {
+                    el.set_attribute(attr, &format!("#{}", rename(target)))?;
+                }
+            }
+            Ok(())
+        })],
+        ..RewriteStrSettings::default()
+    };
+
+    Ok(rewrite_str(html, settings)?)
+}
+
+/// Heading elements, for handlers that match every level
+const HEADINGS: &str = "h1, h2, h3, h4, h5, h6";
+
+/// Shift `<h1>`-`<h6>` heading elements by `delta` levels, clamped to 1..=6
+/// Only real elements are renamed; tag-like text in comments or scripts is left alone.
+pub fn shift_headings(html: &str, delta: i32) -> Result<String> {
+    if delta == 0 {
+        return Ok(html.to_string());
+    }
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![element!(HEADINGS, move |el| {
+            let level = heading_level(&el.tag_name()).unwrap_or(1) as i32;
+            el.set_tag_name(&format!("h{}", level.saturating_add(delta).clamp(1, 6)))?;
+            Ok(())
+        })],
+        ..RewriteStrSettings::default()
+    };
+    Ok(rewrite_str(html, settings)?)
+}
+
+/// The shallowest heading element level in the HTML
+pub fn min_heading_level(html: &str) -> Result<Option<usize>> {
+    let min = Rc::new(RefCell::new(None::<usize>));
+    let min_clone = min.clone();
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!(HEADINGS, move |el| {
+                if let Some(level) = heading_level(&el.tag_name()) {
+                    let mut min = min_clone.borrow_mut();
+                    *min = Some(min.map_or(level, |m| m.min(level)));
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::default()
+        },
+    )?;
+    Ok(*min.borrow())
+}
+
+/// The level of an `hN` tag name
+fn heading_level(tag_name: &str) -> Option<usize> {
+    tag_name
+        .strip_prefix(['h', 'H'])?
+        .parse()
+        .ok()
+        .filter(|level| (1..=6).contains(level))
+}
+
 /// Replace an attribute value while preserving the *-transclude directive
 /// For example: <img src-transclude="logo.png?dataurl"> becomes
 /// <img src-transclude="logo.png?dataurl" src="data:image/png;base64,...">
@@ -326,6 +464,7 @@ mod tests {
             reference: "test.rs#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "code".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -346,6 +485,7 @@ mod tests {
             reference: "test.rs#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "code".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -380,6 +520,7 @@ mod tests {
             reference: "logo.png?dataurl".to_string(),
             attribute_name: "src-transclude".to_string(),
             tag_name: "img".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -400,6 +541,7 @@ mod tests {
             reference: "test.rs#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "pre".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -417,6 +559,7 @@ mod tests {
             reference: "test.rs#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "code".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -434,6 +577,7 @@ mod tests {
             reference: "test.html#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "div".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -452,6 +596,7 @@ mod tests {
             reference: "test.rs#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "pre".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -470,6 +615,7 @@ mod tests {
             reference: "test.html#foo".to_string(),
             attribute_name: "transclude".to_string(),
             tag_name: "div".to_string(),
+            element_id: None,
             element_html: String::new(),
             start_pos: 0,
             end_pos: 0,
@@ -478,4 +624,67 @@ mod tests {
         // Content should NOT be indented because of override
         assert!(result.contains(">line1\nline2</div>"));
     }
+
+    #[test]
+    fn test_rename_ids() {
+        let html = r##"<label for="email">Email</label><input id="email" aria-describedby="email-help other"><p id="email-help">Help</p><a href="#email">jump</a><a href="#elsewhere">x</a>"##;
+        let result = rename_ids(html, |id| format!("signup-{}", id)).unwrap();
+        assert!(result.contains(r#"<label for="signup-email">"#));
+        assert!(result.contains(r#"id="signup-email""#));
+        assert!(result.contains(r#"aria-describedby="signup-email-help other""#));
+        assert!(result.contains(r#"<p id="signup-email-help">"#));
+        assert!(result.contains(r##"<a href="#signup-email">"##));
+        assert!(result.contains(r##"<a href="#elsewhere">"##));
+    }
+
+    #[test]
+    fn test_replace_targets_site_by_element_id() {
+        let html =
+            r#"<div id="a" transclude="f.html#x"></div><div id="b" transclude="f.html#x"></div>"#;
+        let block = TranscludeBlock {
+            reference: "f.html#x".to_string(),
+            attribute_name: "transclude".to_string(),
+            tag_name: "div".to_string(),
+            element_id: Some("b".to_string()),
+            element_html: String::new(),
+            start_pos: 0,
+            end_pos: 0,
+        };
+        let result = replace_inner_html(html, &block, "new", true, Some(false)).unwrap();
+        assert!(result.contains(r#"<div id="a" transclude="f.html#x"></div>"#));
+        assert!(result.contains(r#"<div id="b" transclude="f.html#x">new</div>"#));
+    }
+
+    #[test]
+    fn test_shift_headings() {
+        let html = r#"<h1 class="t">Title</h1><H2>Sub</h2><header>x</header>"#;
+        let result = shift_headings(html, 2).unwrap();
+        assert_eq!(
+            result,
+            r#"<h3 class="t">Title</h3><h4>Sub</h4><header>x</header>"#
+        );
+        assert_eq!(min_heading_level(html).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_shift_headings_skips_non_elements() {
+        let html = "<!-- <h1>old</h1> --><script>s = '<h1>';</script><h2>Real</h2>";
+        assert_eq!(
+            shift_headings(html, 1).unwrap(),
+            "<!-- <h1>old</h1> --><script>s = '<h1>';</script><h3>Real</h3>"
+        );
+        assert_eq!(min_heading_level(html).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_shift_headings_saturates() {
+        assert_eq!(
+            shift_headings("<h2>a</h2>", i32::MAX).unwrap(),
+            "<h6>a</h6>"
+        );
+        assert_eq!(
+            shift_headings("<h2>a</h2>", i32::MIN).unwrap(),
+            "<h1>a</h1>"
+        );
+    }
 }
@@ -0,0 +1,249 @@
+//! Relative link rewriting for transcluded HTML and Markdown
+//!
+//! A relative `href`/`src` inside a transcluded fragment is relative to the file
+//! it came from. When that fragment lands in a host in another directory, the
+//! link targets are rebased so they resolve from the host's location.
+
+use anyhow::Result;
+use lol_html::{RewriteStrSettings, element, rewrite_str};
+use regex::{Captures, Regex};
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+
+use crate::markdown;
+use crate::resolver::{is_html_uri, is_markdown_uri, is_remote_uri};
+
+/// A leading URL scheme (https:, mailto:, data:, ...), which makes a URL absolute
+static SCHEME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9+.\-]*:").unwrap());
+
+/// Rewrite relative links in content transcluded from `source_path` into `host_path`
+/// Both paths are repo-relative. Content is returned unchanged when either side is
+/// not HTML/Markdown, when the source is remote, or when both share a directory.
+pub fn rewrite_for_host(content: &str, source_path: &str, host_path: &str) -> Result<String> {
+    let host_is_markup = is_html_uri(host_path) || is_markdown_uri(host_path);
+    if !host_is_markup || is_remote_uri(source_path) || is_remote_uri(host_path) {
+        return Ok(content.to_string());
+    }
+
+    let source_dir = Path::new(source_path).parent().unwrap_or(Path::new(""));
+    let host_dir = Path::new(host_path).parent().unwrap_or(Path::new(""));
+    if normalize(source_dir) == normalize(host_dir) {
+        return Ok(content.to_string());
+    }
+
+    if is_html_uri(source_path) {
+        rewrite_html(content, source_dir, host_dir)
+    } else if is_markdown_uri(source_path) {
+        Ok(rewrite_markdown(content, source_dir, host_dir))
+    } else {
+        Ok(content.to_string())
+    }
+}
+
+/// Returns true if the URL is relative to the current document's directory
+fn is_relative_url(url: &str) -> bool {
+    let url = url.trim();
+    if url.is_empty() || url.starts_with('/') || url.starts_with('#') || url.starts_with('?') {
+        return false;
+    }
+    !SCHEME.is_match(url)
+}
+
+/// Lexically normalize a relative path, returning None if it climbs above its root
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !result.pop() {
+                    return None;
+                }
+            }
+            Component::Normal(part) => result.push(part),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(result)
+}
+
+/// Express `target` relative to `base_dir` (both normalized, repo-relative)
+fn relative_to(target: &Path, base_dir: &Path) -> String {
+    let target_parts: Vec<_> = target.components().collect();
+    let base_parts: Vec<_> = base_dir.components().collect();
+    let common = target_parts
+        .iter()
+        .zip(&base_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut parts: Vec<String> = Vec::new();
+    for _ in common..base_parts.len() {
+        parts.push("..".to_string());
+    }
+    for part in &target_parts[common..] {
+        parts.push(part.as_os_str().to_string_lossy().to_string());
+    }
+    parts.join("/")
+}
+
+/// Rebase a relative URL from `source_dir` to `host_dir`
+/// Returns None if the URL should be left untouched
+fn rebase_url(url: &str, source_dir: &Path, host_dir: &Path) -> Option<String> {
+    if !is_relative_url(url) {
+        return None;
+    }
+
+    let split = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, suffix) = url.split_at(split);
+    if path.is_empty() {
+        return None;
+    }
+
+    let target = normalize(&source_dir.join(path))?;
+    let host_dir = normalize(host_dir)?;
+    let mut rebased = relative_to(&target, &host_dir);
+    if rebased.is_empty() {
+        rebased = ".".to_string();
+    }
+    if path.ends_with('/') && !rebased.ends_with('/') {
+        rebased.push('/');
+    }
+    Some(format!("{}{}", rebased, suffix))
+}
+
+fn rewrite_html(content: &str, source_dir: &Path, host_dir: &Path) -> Result<String> {
+    let rebase_attr = |attr: &'static str| {
+        let source_dir = source_dir.to_path_buf();
+        let host_dir = host_dir.to_path_buf();
+        move |el: &mut lol_html::html_content::Element| {
+            if let Some(value) = el.get_attribute(attr)
+                && let Some(rebased) = rebase_url(&value, &source_dir, &host_dir)
+            {
+                el.set_attribute(attr, &rebased)?;
+            }
+            Ok(())
+        }
+    };
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("[href]", rebase_attr("href")),
+            element!("[src]", rebase_attr("src")),
+        ],
+        ..RewriteStrSettings::default()
+    };
+
+    Ok(rewrite_str(content, settings)?)
+}
+
+fn rewrite_markdown(content: &str, source_dir: &Path, host_dir: &Path) -> String {
+    // Inline links and images: [text](dest "title") / ![alt](dest)
+    let inline_re =
+        Regex::new(r"(!?\[(?:[^\[\]]|\[[^\]]*\])*\]\(\s*)(<[^>]*>|[^\s()<>]+)").unwrap();
+    // Reference definitions: [id]: dest
+    let definition_re = Regex::new(r"^( {0,3}\[[^\]]+\]:[ \t]*)(<[^>]*>|\S+)").unwrap();
+    // Raw HTML inside Markdown
+    let attribute_re = Regex::new(r#"(\b(?:href|src)\s*=\s*)("[^"]*"|'[^']*')"#).unwrap();
+
+    let rebase_destination = |caps: &Captures| {
+        let prefix = &caps[1];
+        let dest = &caps[2];
+        let (open, inner, close) = if dest.starts_with('<') && dest.ends_with('>') {
+            ("<", &dest[1..dest.len() - 1], ">")
+        } else if (dest.starts_with('"') && dest.ends_with('"'))
+            || (dest.starts_with('\'') && dest.ends_with('\''))
+        {
+            (
+                &dest[..1],
+                &dest[1..dest.len() - 1],
+                &dest[dest.len() - 1..],
+            )
+        } else {
+            ("", dest, "")
+        };
+        match rebase_url(inner, source_dir, host_dir) {
+            Some(rebased) => format!("{}{}{}{}", prefix, open, rebased, close),
+            None => caps[0].to_string(),
+        }
+    };
+
+    markdown::map_prose_lines(content, |line| {
+        markdown::map_outside_code_spans(line, |text| {
+            let text = definition_re.replace(text, &rebase_destination);
+            let text = inline_re.replace_all(&text, &rebase_destination);
+            attribute_re
+                .replace_all(&text, &rebase_destination)
+                .into_owned()
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_url() {
+        let source = Path::new("docs/guide");
+        let host = Path::new("");
+        assert_eq!(
+            rebase_url("img/flow.png", source, host),
+            Some("docs/guide/img/flow.png".to_string())
+        );
+        assert_eq!(
+            rebase_url("../api.html#intro", source, host),
+            Some("docs/api.html#intro".to_string())
+        );
+        assert_eq!(rebase_url("https://example.com/x.png", source, host), None);
+        assert_eq!(rebase_url("/abs.png", source, host), None);
+        assert_eq!(rebase_url("#local", source, host), None);
+        assert_eq!(rebase_url("mailto:a@b.c", source, host), None);
+    }
+
+    #[test]
+    fn test_rebase_url_into_sibling_directory() {
+        assert_eq!(
+            rebase_url("img/a.png", Path::new("docs/guide"), Path::new("docs/api")),
+            Some("../guide/img/a.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rewrite_markdown_links() {
+        let content = "![diagram](img/flow.png) and [API](../api.html \"API\")\n\
+                       [ref]: ../ref.md\n\
+                       `[code](img/x.png)`\n\
+                       ```\n[fenced](img/x.png)\n```\n";
+        let result = rewrite_for_host(content, "docs/guide/intro.md", "README.md").unwrap();
+        assert!(result.contains("![diagram](docs/guide/img/flow.png)"));
+        assert!(result.contains("[API](docs/api.html \"API\")"));
+        assert!(result.contains("[ref]: docs/ref.md"));
+        assert!(result.contains("`[code](img/x.png)`"));
+        assert!(result.contains("[fenced](img/x.png)"));
+    }
+
+    #[test]
+    fn test_rewrite_html_attributes() {
+        let content =
+            r#"<a href="../api.html">API</a><img src="img/flow.png"><a href="https://x.io">x</a>"#;
+        let result = rewrite_for_host(content, "docs/guide/intro.html", "index.html").unwrap();
+        assert!(result.contains(r#"<a href="docs/api.html">"#));
+        assert!(result.contains(r#"<img src="docs/guide/img/flow.png">"#));
+        assert!(result.contains(r#"<a href="https://x.io">"#));
+    }
+
+    #[test]
+    fn test_rewrite_skipped_for_same_directory_and_code_hosts() {
+        let content = "[a](b.md)";
+        assert_eq!(
+            rewrite_for_host(content, "docs/a.md", "docs/index.md").unwrap(),
+            content
+        );
+        assert_eq!(
+            rewrite_for_host(content, "docs/a.md", "src/lib.rs").unwrap(),
+            content
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Encoding of resolved content as a literal for the host language (`?as=...`)
+//!
+//! Each encoder produces a complete, valid literal. Where the literal needs a
+//! delimiter (raw strings, heredocs) the shortest one that cannot collide with
+//! the content is chosen automatically.
+
+use anyhow::{Result, anyhow};
+
+/// Encode content as the named literal form
+pub fn encode(content: &str, target: &str) -> Result<String> {
+    match target {
+        "rust-str" => Ok(rust_str(content)),
+        "raw-rust-str" => raw_rust_str(content),
+        "json-str" => Ok(json_str(content)),
+        "yaml-block" => Ok(yaml_block(content)),
+        "shell-heredoc" => Ok(shell_heredoc(content)),
+        other => Err(anyhow!(
+            "Unknown literal form '{}' (expected rust-str, raw-rust-str, json-str, yaml-block or shell-heredoc)",
+            other
+        )),
+    }
+}
+
+/// A Rust string literal, one source line per content line
+/// Lines end in `\n\` so the literal survives re-indentation; a leading space or
+/// tab is escaped because Rust skips whitespace after a line continuation.
+fn rust_str(content: &str) -> String {
+    let escape_char = |c: char, out: &mut String| match c {
+        '\\' => out.push_str("\\\\"),
+        '"' => out.push_str("\\\""),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\0' => out.push_str("\\0"),
+        c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+        c => out.push(c),
+    };
+
+    let mut out = String::from("\"");
+    let lines: Vec<&str> = content.split('\n').collect();
+    for (i, line) in lines.iter().enumerate() {
+        let mut chars = line.chars();
+        if i > 0
+            && let Some(first) = chars.next()
+        {
+            match first {
+                ' ' => out.push_str("\\x20"),
+                c => escape_char(c, &mut out),
+            }
+        }
+        for c in chars {
+            escape_char(c, &mut out);
+        }
+        if i + 1 < lines.len() {
+            out.push_str("\\n\\\n");
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A Rust raw string literal with the fewest `#`s that keep it unambiguous
+fn raw_rust_str(content: &str) -> Result<String> {
+    if content.contains('\r') {
+        return Err(anyhow!(
+            "raw-rust-str cannot represent carriage returns; use ?as=rust-str"
+        ));
+    }
+
+    // The literal ends at `"` followed by N hashes, so N must exceed every such run
+    let mut hashes = 0;
+    for (i, _) in content.match_indices('"') {
+        let run = content[i + 1..].chars().take_while(|&c| c == '#').count();
+        hashes = hashes.max(run + 1);
+    }
+
+    let fence = "#".repeat(hashes);
+    Ok(format!("r{}\"{}\"{}", fence, content, fence))
+}
+
+/// A JSON string literal (RFC 8259), always on a single line
+fn json_str(content: &str) -> String {
+    let mut out = String::from("\"");
+    for c in content.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A YAML literal block scalar (`|`), indented two spaces under its header
+/// Content whose first line starts with whitespace would need an indentation
+/// indicator relative to the (unknown) parent node, so it falls back to a
+/// double-quoted scalar, which YAML shares with JSON.
+fn yaml_block(content: &str) -> String {
+    let first_line = content.lines().find(|l| !l.trim().is_empty());
+    let needs_quoting = content.is_empty()
+        || first_line.is_none_or(|l| l.starts_with([' ', '\t']))
+        || content.contains('\r')
+        || content
+            .chars()
+            .any(|c| c.is_control() && c != '\n' && c != '\t');
+    if needs_quoting {
+        return json_str(content);
+    }
+
+    // Hosts are rewritten line by line, so trailing blank lines can't be kept;
+    // only the presence of a final newline is encoded (clip vs strip chomping)
+    let chomping = if content.ends_with('\n') { "" } else { "-" };
+
+    let mut out = format!("|{}", chomping);
+    for line in content.trim_end_matches('\n').split('\n') {
+        out.push('\n');
+        if !line.is_empty() {
+            out.push_str("  ");
+            out.push_str(line);
+        }
+    }
+    out
+}
+
+/// A quoted shell heredoc that writes the content to stdout
+/// The delimiter is quoted so nothing in the body is expanded.
+fn shell_heredoc(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut delimiter = "EOF".to_string();
+    let mut n = 0;
+    while lines.iter().any(|l| *l == delimiter) {
+        n += 1;
+        delimiter = format!("EOF_{}", n);
+    }
+
+    let body = content.strip_suffix('\n').unwrap_or(content);
+    format!("cat <<'{}'\n{}\n{}", delimiter, body, delimiter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rust_str() {
+        assert_eq!(rust_str(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+        assert_eq!(
+            rust_str("line one\n  indented\n"),
+            "\"line one\\n\\\n\\x20 indented\\n\\\n\""
+        );
+    }
+
+    #[test]
+    fn test_raw_rust_str_hashes() {
+        assert_eq!(raw_rust_str("plain").unwrap(), r#"r"plain""#);
+        assert_eq!(raw_rust_str(r#"a "quote""#).unwrap(), r##"r#"a "quote""#"##);
+        assert_eq!(
+            raw_rust_str(r###"ends "## here"###).unwrap(),
+            r####"r###"ends "## here"###"####
+        );
+        assert!(raw_rust_str("a\r\nb").is_err());
+    }
+
+    #[test]
+    fn test_json_str() {
+        assert_eq!(
+            json_str("SELECT \"x\"\n\tFROM t\u{1}"),
+            r#""SELECT \"x\"\n\tFROM t\u0001""#
+        );
+    }
+
+    #[test]
+    fn test_yaml_block() {
+        assert_eq!(yaml_block("a\n\nb"), "|-\n  a\n\n  b");
+        assert_eq!(yaml_block("a\n"), "|\n  a");
+        // Leading indentation would be misread without an indicator
+        assert_eq!(yaml_block("  a\nb"), r#""  a\nb""#);
+    }
+
+    #[test]
+    fn test_shell_heredoc_delimiter() {
+        assert_eq!(
+            shell_heredoc("echo $HOME\n"),
+            "cat <<'EOF'\necho $HOME\nEOF"
+        );
+        assert_eq!(
+            shell_heredoc("EOF\nEOF_1"),
+            "cat <<'EOF_2'\nEOF\nEOF_1\nEOF_2"
+        );
+    }
+
+    #[test]
+    fn test_unknown_form() {
+        assert!(encode("x", "toml-str").is_err());
+    }
+}
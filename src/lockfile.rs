@@ -0,0 +1,214 @@
+//! `liaison.lock`: content hashes of remote sources
+//!
+//! Every remote source is recorded with its URL, the SHA-256 of the fetched
+//! body and when it was fetched. A later fetch that doesn't match is an error
+//! until `liaison update` accepts the new content, so upstream drift shows up
+//! as a lockfile diff in review rather than silently in the output.
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::http::{redact_url, sha256_hex};
+
+/// File name of the lockfile, at the repository root
+pub const LOCKFILE: &str = "liaison.lock";
+
+const HEADER: &str =
+    "# Generated by liaison. Refresh entries with `liaison update [URL-GLOB]`.\n\n";
+
+/// One locked remote source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub url: String,
+    pub sha256: String,
+    /// RFC 3339 UTC time of the fetch that produced the hash
+    pub fetched: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LockData {
+    #[serde(default, rename = "source")]
+    sources: Vec<Source>,
+}
+
+#[derive(Debug, Default)]
+pub struct Lockfile {
+    sources: BTreeMap<String, Source>,
+    /// URLs seen during this run, so unused entries can be pruned
+    used: BTreeSet<String>,
+    /// URLs whose entries may be replaced (`liaison update`)
+    update: Option<glob::Pattern>,
+    changed: bool,
+}
+
+impl Lockfile {
+    /// Load the lockfile, or start an empty one if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let data: LockData =
+            toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        Ok(Self {
+            sources: data
+                .sources
+                .into_iter()
+                .map(|s| (s.url.clone(), s))
+                .collect(),
+            ..Self::default()
+        })
+    }
+
+    /// Allow entries for URLs matching the glob to be replaced
+    pub fn set_update(&mut self, pattern: &str) -> Result<()> {
+        let pattern = glob::Pattern::new(pattern)
+            .with_context(|| format!("Invalid URL pattern: {}", pattern))?;
+        self.update = Some(pattern);
+        Ok(())
+    }
+
+    /// True if this URL's entry is being refreshed
+    pub fn wants_update(&self, url: &str) -> bool {
+        self.update.as_ref().is_some_and(|p| p.matches(url))
+    }
+
+    /// Check fetched content against the lock, recording it if it's new
+    /// URLs are recorded with any password redacted, since the lockfile is committed.
+    pub fn verify(&mut self, url: &str, body: &str) -> Result<()> {
+        let url = &redact_url(url);
+        self.used.insert(url.to_string());
+        let sha256 = sha256_hex(body.as_bytes());
+        match self.sources.get(url) {
+            Some(locked) if locked.sha256 == sha256 => return Ok(()),
+            Some(locked) if !self.wants_update(url) => {
+                return Err(anyhow!(
+                    "Content of {} differs from {} (locked sha256 {}, fetched {}); run `liaison update '{}'` to accept it",
+                    url,
+                    LOCKFILE,
+                    locked.sha256,
+                    sha256,
+                    url
+                ));
+            }
+            _ => {}
+        }
+        self.sources.insert(
+            url.to_string(),
+            Source {
+                url: url.to_string(),
+                sha256,
+                fetched: timestamp(now()),
+            },
+        );
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Drop entries for URLs that weren't fetched in this run
+    /// Only meaningful after a run over every file.
+    pub fn prune(&mut self) {
+        let before = self.sources.len();
+        self.sources.retain(|url, _| self.used.contains(url));
+        self.changed |= self.sources.len() != before;
+    }
+
+    /// True if the lockfile needs to be written
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = LockData {
+            sources: self.sources.values().cloned().collect(),
+        };
+        let text = format!("{}{}", HEADER, toml::to_string(&data)?);
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format Unix seconds as an RFC 3339 UTC timestamp
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_and_update() {
+        let url = "https://example.com/snippet.rs";
+        let mut lock = Lockfile::default();
+        lock.verify(url, "v1").unwrap();
+        assert!(lock.is_changed());
+        lock.verify(url, "v1").unwrap();
+
+        let err = lock.verify(url, "v2").unwrap_err().to_string();
+        assert!(err.contains("differs from liaison.lock"));
+        assert!(err.contains(&sha256_hex(b"v2")));
+
+        lock.set_update("https://example.com/*").unwrap();
+        lock.verify(url, "v2").unwrap();
+        assert_eq!(lock.sources[url].sha256, sha256_hex(b"v2"));
+    }
+
+    #[test]
+    fn test_round_trip_and_prune() {
+        let path = std::env::temp_dir().join(format!("liaison-{}.lock", std::process::id()));
+        let mut lock = Lockfile::default();
+        lock.verify("https://a.example/x", "x").unwrap();
+        lock.verify("https://b.example/y", "y").unwrap();
+        lock.save(&path).unwrap();
+
+        let mut loaded = Lockfile::load(&path).unwrap();
+        assert_eq!(loaded.sources, lock.sources);
+        assert!(!loaded.is_changed());
+        loaded.verify("https://a.example/x", "x").unwrap();
+        loaded.prune();
+        assert!(loaded.is_changed());
+        assert_eq!(loaded.sources.len(), 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_792_325_045), "2026-10-18T12:04:05Z");
+    }
+}
@@ -0,0 +1,318 @@
+//! Line-oriented Markdown helpers
+//!
+//! Transforms that touch Markdown prose (links, headings) must leave fenced
+//! code blocks and inline code spans alone. These helpers walk the content and
+//! only hand the prose parts to the caller.
+
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Returns the fence marker (e.g. "```" or "~~~~") if the line opens or closes a fenced code block
+fn fence_marker(line: &str) -> Option<&str> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let ch = rest.chars().next()?;
+    if ch != '`' && ch != '~' {
+        return None;
+    }
+    let len = rest.len() - rest.trim_start_matches(ch).len();
+    if len >= 3 { Some(&rest[..len]) } else { None }
+}
+
+/// Split content into lines, keeping their endings, each flagged true if it is
+/// prose (outside a fenced code block and not a fence line itself)
+fn classify_lines(content: &str) -> Vec<(&str, bool)> {
+    let mut lines = Vec::new();
+    let mut open_fence: Option<&str> = None;
+
+    for raw_line in content.split_inclusive('\n') {
+        let line = raw_line.trim_end_matches(['\n', '\r']);
+        let prose = match (open_fence, fence_marker(line)) {
+            (None, Some(marker)) => {
+                open_fence = Some(marker);
+                false
+            }
+            (Some(open), Some(marker))
+                if marker.starts_with(open)
+                    && line.trim().trim_start_matches(marker).is_empty() =>
+            {
+                open_fence = None;
+                false
+            }
+            (Some(_), _) => false,
+            (None, None) => true,
+        };
+        lines.push((raw_line, prose));
+    }
+
+    lines
+}
+
+/// A line without its ending, and the ending
+fn split_ending(raw_line: &str) -> (&str, &str) {
+    let line = raw_line.trim_end_matches(['\n', '\r']);
+    (line, &raw_line[line.len()..])
+}
+
+/// Apply `f` to every line that is outside a fenced code block
+/// Line endings are preserved exactly; `f` receives the line without its newline
+pub fn map_prose_lines(content: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut result = String::with_capacity(content.len());
+    for (raw_line, prose) in classify_lines(content) {
+        if prose {
+            let (line, ending) = split_ending(raw_line);
+            result.push_str(&f(line));
+            result.push_str(ending);
+        } else {
+            result.push_str(raw_line);
+        }
+    }
+    result
+}
+
+/// Apply `f` to the parts of a line that are outside inline code spans
+pub fn map_outside_code_spans(line: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('`') {
+        let run = rest[start..].len() - rest[start..].trim_start_matches('`').len();
+        let delimiter = &rest[start..start + run];
+        let after_open = start + run;
+
+        match rest[after_open..].find(delimiter) {
+            Some(close) => {
+                let end = after_open + close + run;
+                result.push_str(&f(&rest[..start]));
+                result.push_str(&rest[start..end]);
+                rest = &rest[end..];
+            }
+            None => {
+                // Unmatched backticks are literal text
+                result.push_str(&f(&rest[..after_open]));
+                rest = &rest[after_open..];
+            }
+        }
+    }
+
+    result.push_str(&f(rest));
+    result
+}
+
+static ATX_HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^( {0,3})(#{1,6})([ \t]|$)").unwrap());
+
+/// The underline of a Setext heading: `===` for level 1, `---` for level 2
+static SETEXT_UNDERLINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}(=+|-+)[ \t]*$").unwrap());
+
+static THEMATIC_BREAK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}(?:(?:-[ \t]*){3,}|(?:\*[ \t]*){3,}|(?:_[ \t]*){3,})$").unwrap()
+});
+
+/// Block quote and list item openers, whose paragraphs a Setext underline can't reach
+static CONTAINER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}(?:>|[-+*](?:[ \t]|$)|\d{1,9}[.)](?:[ \t]|$))").unwrap());
+
+/// A Setext heading: the paragraph on lines `text`, underlined on the line after
+struct Setext {
+    text: std::ops::Range<usize>,
+    level: usize,
+}
+
+fn is_paragraph_line(line: &str) -> bool {
+    !line.trim().is_empty()
+        && !ATX_HEADING.is_match(line)
+        && !THEMATIC_BREAK.is_match(line)
+        && !CONTAINER.is_match(line)
+}
+
+/// Find the Setext headings among classified lines
+fn setext_headings(lines: &[(&str, bool)]) -> Vec<Setext> {
+    let text = |i: usize| split_ending(lines[i].0).0;
+    let mut headings = Vec::new();
+    // Lines before this one already belong to a heading
+    let mut claimed = 0;
+
+    for i in 1..lines.len() {
+        if !lines[i].1 {
+            continue;
+        }
+        let Some(caps) = SETEXT_UNDERLINE.captures(text(i)) else {
+            continue;
+        };
+
+        let mut start = i;
+        while start > claimed && lines[start - 1].1 && is_paragraph_line(text(start - 1)) {
+            start -= 1;
+        }
+        // No paragraph above means `---` is a thematic break; a paragraph that
+        // continues a container or starts as indented code is not a heading
+        let indented_code = text(start).starts_with("    ") || text(start).starts_with('\t');
+        let in_container =
+            start > claimed && lines[start - 1].1 && CONTAINER.is_match(text(start - 1));
+        if start == i || indented_code || in_container {
+            continue;
+        }
+
+        let level = if caps[1].starts_with('=') { 1 } else { 2 };
+        headings.push(Setext {
+            text: start..i,
+            level,
+        });
+        claimed = i + 1;
+    }
+
+    headings
+}
+
+/// `level` moved by `delta`, clamped to 1..=6 without overflowing
+fn shifted(level: usize, delta: i32) -> usize {
+    (level as i32).saturating_add(delta).clamp(1, 6) as usize
+}
+
+/// The shallowest heading level in the content, ATX or Setext, ignoring fenced code
+pub fn min_heading_level(content: &str) -> Option<usize> {
+    let lines = classify_lines(content);
+    let atx = lines
+        .iter()
+        .filter(|(_, prose)| *prose)
+        .filter_map(|(raw_line, _)| ATX_HEADING.captures(raw_line))
+        .map(|caps| caps[2].len());
+    let setext = setext_headings(&lines)
+        .into_iter()
+        .map(|heading| heading.level);
+    atx.chain(setext).min()
+}
+
+/// Shift heading levels by `delta`, clamped to 1..=6
+/// Setext headings keep their underline while they stay at level 1 or 2 and
+/// become ATX headings below that. Headings inside fenced code blocks are left alone.
+pub fn shift_headings(content: &str, delta: i32) -> String {
+    if delta == 0 {
+        return content.to_string();
+    }
+
+    let lines = classify_lines(content);
+    let mut setext = setext_headings(&lines).into_iter().peekable();
+    let mut result = String::with_capacity(content.len());
+    let mut i = 0;
+
+    while i < lines.len() {
+        if let Some(heading) = setext.next_if(|heading| heading.text.start == i) {
+            let level = shifted(heading.level, delta);
+            let (underline, ending) = split_ending(lines[heading.text.end].0);
+            if level <= 2 {
+                for (raw_line, _) in &lines[heading.text.clone()] {
+                    result.push_str(raw_line);
+                }
+                let mark = if level == 1 { "=" } else { "-" };
+                result.push_str(&underline.replace(['=', '-'], mark));
+            } else {
+                let title: Vec<&str> = lines[heading.text.clone()]
+                    .iter()
+                    .map(|(raw_line, _)| raw_line.trim())
+                    .collect();
+                result.push_str(&format!("{} {}", "#".repeat(level), title.join(" ")));
+            }
+            result.push_str(ending);
+            i = heading.text.end + 1;
+            continue;
+        }
+
+        let (raw_line, prose) = lines[i];
+        if prose {
+            let (line, ending) = split_ending(raw_line);
+            let line = ATX_HEADING.replace(line, |caps: &regex::Captures| {
+                let level = shifted(caps[2].len(), delta);
+                format!("{}{}{}", &caps[1], "#".repeat(level), &caps[3])
+            });
+            result.push_str(&line);
+            result.push_str(ending);
+        } else {
+            result.push_str(raw_line);
+        }
+        i += 1;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_prose_lines_skips_fences() {
+        let content = "a\n```rust\na\n```\na\n";
+        let result = map_prose_lines(content, |line| line.replace('a', "b"));
+        assert_eq!(result, "b\n```rust\na\n```\nb\n");
+    }
+
+    #[test]
+    fn test_map_prose_lines_longer_fence() {
+        let content = "````\n```\na\n````\na";
+        let result = map_prose_lines(content, |line| line.replace('a', "b"));
+        assert_eq!(result, "````\n```\na\n````\nb");
+    }
+
+    #[test]
+    fn test_map_outside_code_spans() {
+        let result = map_outside_code_spans("a `a` a ``a`` a", |s| s.replace('a', "b"));
+        assert_eq!(result, "b `a` b ``a`` b");
+    }
+
+    #[test]
+    fn test_shift_headings() {
+        let content = "# Title\n\n## Section\n\n```sh\n# comment\n```\n#hashtag\n";
+        let result = shift_headings(content, 2);
+        assert_eq!(
+            result,
+            "### Title\n\n#### Section\n\n```sh\n# comment\n```\n#hashtag\n"
+        );
+    }
+
+    #[test]
+    fn test_shift_headings_clamps() {
+        assert_eq!(shift_headings("##### Deep", 3), "###### Deep");
+        assert_eq!(shift_headings("## Up", -4), "# Up");
+    }
+
+    #[test]
+    fn test_shift_headings_saturates() {
+        assert_eq!(shift_headings("## a", i32::MAX), "###### a");
+        assert_eq!(shift_headings("## a", i32::MIN), "# a");
+    }
+
+    #[test]
+    fn test_shift_setext_headings() {
+        let content = "Title\n=====\n\nSection\nspans two lines\n---\n\ntext\n\n---\n";
+        assert_eq!(
+            shift_headings(content, 1),
+            "Title\n-----\n\n### Section spans two lines\n\ntext\n\n---\n"
+        );
+        assert_eq!(
+            shift_headings(content, 2),
+            "### Title\n\n#### Section spans two lines\n\ntext\n\n---\n"
+        );
+        assert_eq!(shift_headings("## Up\nA\n---\n", -1), "# Up\nA\n===\n");
+        assert_eq!(min_heading_level(content), Some(1));
+    }
+
+    #[test]
+    fn test_setext_underline_needs_a_paragraph() {
+        // A list item, a thematic break and fenced code are not underlined headings
+        let content = "- item\n---\n\n***\n---\n\n```\ncode\n---\n```\n";
+        assert_eq!(shift_headings(content, 2), content);
+        assert_eq!(min_heading_level(content), None);
+    }
+
+    #[test]
+    fn test_min_heading_level() {
+        assert_eq!(min_heading_level("### a\n## b\n```\n# c\n```"), Some(2));
+        assert_eq!(min_heading_level("no headings"), None);
+    }
+}
@@ -0,0 +1,285 @@
+//! Built-in project metadata sources
+//!
+//! `cargo:package.version`, `npm:version`, `git:describe`, `git:short-sha` and
+//! `env:NAME` resolve to a single value so version numbers and crate names can
+//! be kept in one place.
+
+use anyhow::{Context, Result, anyhow};
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Config;
+
+/// URI schemes handled here
+const SCHEMES: &[&str] = &["cargo:", "npm:", "git:", "env:"];
+
+/// Returns true if the URI names a metadata source
+pub fn is_metadata(uri: &str) -> bool {
+    SCHEMES.iter().any(|scheme| uri.starts_with(scheme))
+}
+
+/// Resolve a metadata URI to its value
+/// `manifest` overrides the Cargo.toml or package.json that is read (repo-relative).
+pub fn resolve(
+    uri: &str,
+    manifest: Option<&str>,
+    repo_root: &Path,
+    config: &Config,
+) -> Result<String> {
+    let (scheme, key) = uri
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Not a metadata source: {}", uri))?;
+    if key.is_empty() {
+        return Err(anyhow!("{}: needs a key: {}", scheme, uri));
+    }
+
+    match scheme {
+        "cargo" => cargo(repo_root, manifest.unwrap_or("Cargo.toml"), key),
+        "npm" => npm(repo_root, manifest.unwrap_or("package.json"), key),
+        "git" => git(repo_root, key),
+        "env" => env(key, &config.env.allow),
+        _ => Err(anyhow!("Not a metadata source: {}", uri)),
+    }
+}
+
+/// Look up a dotted key in a Cargo manifest, following `workspace = true`
+/// inheritance into the workspace root's `[workspace.package]` and
+/// `[workspace.dependencies]`
+fn cargo(repo_root: &Path, manifest: &str, key: &str) -> Result<String> {
+    let manifest_path = repo_root.join(manifest);
+    let document = read_toml(&manifest_path)?;
+    let segments: Vec<&str> = key.split('.').collect();
+
+    let mut current = toml::Value::Table(document);
+    for (i, segment) in segments.iter().enumerate() {
+        current = current
+            .get(segment)
+            .cloned()
+            .ok_or_else(|| anyhow!("{} has no key '{}'", manifest, key))?;
+
+        let inherited = current
+            .get("workspace")
+            .and_then(|w| w.as_bool())
+            .unwrap_or(false);
+        if inherited {
+            let mut workspace_key = match segments[0] {
+                "package" => vec!["workspace", "package"],
+                "dependencies" | "dev-dependencies" | "build-dependencies" => {
+                    vec!["workspace", "dependencies"]
+                }
+                _ => return Err(anyhow!("Unexpected workspace inheritance at '{}'", key)),
+            };
+            workspace_key.extend(&segments[1..=i]);
+            let root = find_workspace_root(repo_root, &manifest_path)?;
+            current = lookup(&toml::Value::Table(root), &workspace_key)
+                .with_context(|| format!("Resolving inherited '{}' in {}", key, manifest))?;
+
+            // A workspace dependency given as a plain string is its version
+            if let toml::Value::String(version) = &current
+                && segments[i + 1..] == ["version"]
+            {
+                return Ok(version.clone());
+            }
+            current = lookup(&current, &segments[i + 1..])
+                .with_context(|| format!("Resolving inherited '{}' in {}", key, manifest))?;
+            break;
+        }
+    }
+
+    scalar_toml(&current, key)
+}
+
+fn lookup(value: &toml::Value, path: &[&str]) -> Result<toml::Value> {
+    let mut current = value;
+    for segment in path {
+        current = current
+            .get(segment)
+            .ok_or_else(|| anyhow!("Workspace has no key '{}'", path.join(".")))?;
+    }
+    Ok(current.clone())
+}
+
+fn read_toml(path: &Path) -> Result<toml::Table> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    content
+        .parse::<toml::Table>()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Walk up from the manifest to the repository root for a `[workspace]` manifest
+fn find_workspace_root(repo_root: &Path, manifest_path: &Path) -> Result<toml::Table> {
+    let mut dir = manifest_path.parent();
+    while let Some(current) = dir {
+        let candidate = current.join("Cargo.toml");
+        if candidate.is_file() {
+            let document = read_toml(&candidate)?;
+            if document.contains_key("workspace") {
+                return Ok(document);
+            }
+        }
+        if current == repo_root {
+            break;
+        }
+        dir = current.parent();
+    }
+    Err(anyhow!(
+        "No workspace root found for {}",
+        manifest_path.display()
+    ))
+}
+
+fn scalar_toml(value: &toml::Value, key: &str) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(n) => Ok(n.to_string()),
+        toml::Value::Float(n) => Ok(n.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        _ => Err(anyhow!("'{}' is not a single value", key)),
+    }
+}
+
+/// Look up a dotted key in package.json
+fn npm(repo_root: &Path, manifest: &str, key: &str) -> Result<String> {
+    let path = repo_root.join(manifest);
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let document: serde_json::Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let mut current = &document;
+    for segment in key.split('.') {
+        current = current
+            .get(segment)
+            .ok_or_else(|| anyhow!("{} has no key '{}'", manifest, key))?;
+    }
+
+    match current {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(anyhow!("'{}' is not a single value", key)),
+    }
+}
+
+/// Repository facts from git
+fn git(repo_root: &Path, what: &str) -> Result<String> {
+    let args: &[&str] = match what {
+        "describe" => &["describe", "--tags", "--always"],
+        "sha" => &["rev-parse", "HEAD"],
+        "short-sha" => &["rev-parse", "--short", "HEAD"],
+        "branch" => &["rev-parse", "--abbrev-ref", "HEAD"],
+        other => {
+            return Err(anyhow!(
+                "Unknown git: source '{}' (expected describe, sha, short-sha or branch)",
+                other
+            ));
+        }
+    };
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(repo_root)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git:{} failed: {}",
+            what,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// An environment variable, if allowlisted in `[env]`
+fn env(name: &str, allow: &[String]) -> Result<String> {
+    if !allow.iter().any(|a| a == name) {
+        return Err(anyhow!(
+            "Environment variable is not allowlisted in [env] allow in .liaison.toml: {}",
+            name
+        ));
+    }
+    std::env::var(name).with_context(|| format!("Environment variable {} is not set", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("liaison-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_cargo_workspace_inheritance() {
+        let root = fixture(
+            "cargo-meta",
+            &[
+                (
+                    "Cargo.toml",
+                    "[workspace]\nmembers = [\"crates/*\"]\n\n\
+                     [workspace.package]\nversion = \"1.4.0\"\nrust-version = \"1.80\"\n\n\
+                     [workspace.dependencies]\nserde = \"1.0.200\"\n",
+                ),
+                (
+                    "crates/core/Cargo.toml",
+                    "[package]\nname = \"core\"\nversion.workspace = true\n\n\
+                     [dependencies]\nserde = { workspace = true, features = [\"derive\"] }\n",
+                ),
+            ],
+        );
+        let manifest = "crates/core/Cargo.toml";
+        assert_eq!(cargo(&root, manifest, "package.name").unwrap(), "core");
+        assert_eq!(cargo(&root, manifest, "package.version").unwrap(), "1.4.0");
+        assert_eq!(
+            cargo(&root, manifest, "dependencies.serde.version").unwrap(),
+            "1.0.200"
+        );
+        assert_eq!(
+            cargo(&root, "Cargo.toml", "workspace.package.rust-version").unwrap(),
+            "1.80"
+        );
+        assert!(cargo(&root, manifest, "package.edition").is_err());
+        assert!(cargo(&root, manifest, "dependencies").is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_npm() {
+        let root = fixture(
+            "npm-meta",
+            &[(
+                "package.json",
+                r#"{"name": "@me/tool", "version": "2.1.0", "engines": {"node": ">=20"}}"#,
+            )],
+        );
+        assert_eq!(npm(&root, "package.json", "version").unwrap(), "2.1.0");
+        assert_eq!(npm(&root, "package.json", "engines.node").unwrap(), ">=20");
+        assert!(npm(&root, "package.json", "engines").is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_env_requires_allowlist() {
+        assert!(env("PATH", &[]).is_err());
+        assert_eq!(
+            env("PATH", &["PATH".to_string()]).unwrap(),
+            std::env::var("PATH").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unknown_git_source() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert!(git(root, "tags").is_err());
+    }
+}
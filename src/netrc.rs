@@ -0,0 +1,91 @@
+//! Credentials from `~/.netrc` (or the file named by `$NETRC`)
+
+use std::path::PathBuf;
+
+/// Login and password for a host
+pub struct Credentials {
+    pub login: String,
+    pub password: Option<String>,
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    std::env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc")))
+}
+
+/// Look up a host in the user's netrc file
+pub fn lookup(host: &str) -> Option<Credentials> {
+    let text = std::fs::read_to_string(netrc_path()?).ok()?;
+    find(&text, host)
+}
+
+/// Find the entry for a host, falling back to a `default` entry
+fn find(text: &str, host: &str) -> Option<Credentials> {
+    let mut tokens = text.split_whitespace();
+    let mut current: Option<bool> = None; // Some(true) if the entry is for this host
+    let mut login = None;
+    let mut password = None;
+    let mut fallback = None;
+
+    let mut finish = |is_host: Option<bool>, login: Option<String>, password: Option<String>| {
+        let credentials = login.map(|login| Credentials { login, password });
+        match is_host {
+            Some(true) => credentials,
+            Some(false) => None,
+            None => {
+                if fallback.is_none() {
+                    fallback = credentials;
+                }
+                None
+            }
+        }
+    };
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" | "default" => {
+                if (current.is_some() || login.is_some())
+                    && let Some(found) = finish(current, login.take(), password.take())
+                {
+                    return Some(found);
+                }
+                current = match token {
+                    "machine" => Some(tokens.next() == Some(host)),
+                    _ => None,
+                };
+            }
+            "login" => login = tokens.next().map(str::to_string),
+            "password" => password = tokens.next().map(str::to_string),
+            "account" => {
+                tokens.next();
+            }
+            // Macro definitions run to a blank line, which whitespace splitting can't see
+            "macdef" => break,
+            _ => {}
+        }
+    }
+
+    finish(current, login, password).or(fallback)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let text = "machine a.example login alice password s3cret\n\
+                    machine b.example\n  login bob\n\
+                    default login anonymous password guest\n";
+        let a = find(text, "a.example").unwrap();
+        assert_eq!(
+            (a.login.as_str(), a.password.as_deref()),
+            ("alice", Some("s3cret"))
+        );
+        let b = find(text, "b.example").unwrap();
+        assert_eq!((b.login.as_str(), b.password.as_deref()), ("bob", None));
+        assert_eq!(find(text, "c.example").unwrap().login, "anonymous");
+        assert!(find("machine a.example login alice", "c.example").is_none());
+    }
+}
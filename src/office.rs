@@ -0,0 +1,950 @@
+//! Office document sources: Word text and spreadsheet ranges
+//!
+//! `.docx` files yield the paragraphs under a heading (`#heading:Goals`) or
+//! inside a bookmark (`#bookmark:scope`). `.xlsx` and `.ods` files yield a
+//! cell range (`#Sheet1!A1:C10`), a whole sheet (`#Sheet1`) or a named range
+//! (`#Pricing`). Documents are read straight from their ZIP/XML parts and
+//! rendered as Markdown or HTML.
+
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+use roxmltree::{Document, Node};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read};
+
+use crate::html::escape_html;
+use crate::table::{self, Format};
+
+/// Returns true if the path is a supported office document
+pub fn is_office(uri: &str) -> bool {
+    uri.ends_with(".docx") || uri.ends_with(".xlsx") || uri.ends_with(".ods")
+}
+
+/// Extract the selected part of an office document in the given format
+pub fn extract(bytes: &[u8], uri: &str, selector: Option<&str>, format: Format) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+        .with_context(|| format!("{} is not a valid office document", uri))?;
+
+    if uri.ends_with(".docx") {
+        let blocks = docx_blocks(&mut archive, uri, selector)?;
+        match format {
+            Format::Markdown => Ok(blocks_to_markdown(&blocks)),
+            Format::Html => Ok(blocks_to_html(&blocks)),
+            Format::Csv => Err(anyhow!("Word documents can't be rendered as CSV: {}", uri)),
+        }
+    } else {
+        let rows = if uri.ends_with(".xlsx") {
+            xlsx_range(&mut archive, uri, selector)?
+        } else {
+            ods_range(&mut archive, uri, selector)?
+        };
+
+        // A single cell is a value, not a table, so it can sit inside a sentence
+        if let [row] = rows.as_slice()
+            && let [cell] = row.as_slice()
+        {
+            return Ok(if format == Format::Html {
+                escape_html(cell)
+            } else {
+                cell.clone()
+            });
+        }
+        Ok(table::render(&rows, format))
+    }
+}
+
+type Archive<'a> = zip::ZipArchive<Cursor<&'a [u8]>>;
+
+fn read_part(archive: &mut Archive, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    Ok(Some(text))
+}
+
+fn require_part(archive: &mut Archive, name: &str, uri: &str) -> Result<String> {
+    read_part(archive, name)?.ok_or_else(|| anyhow!("{} has no {}", uri, name))
+}
+
+/// Attribute by local name, ignoring its namespace prefix
+fn attr<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+}
+
+fn is(node: Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| is(*n, name))
+}
+
+// -----------------------------------------------------------------------------
+// Word documents
+// -----------------------------------------------------------------------------
+
+/// A run of text with its emphasis
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    text: String,
+    bold: bool,
+    italic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Heading(usize, Vec<Span>),
+    Paragraph(Vec<Span>),
+    ListItem(usize, Vec<Span>),
+    Table(Vec<Vec<String>>),
+}
+
+fn plain_text(spans: &[Span]) -> String {
+    spans.iter().map(|s| s.text.as_str()).collect()
+}
+
+/// A `w:b`/`w:i` toggle is on unless its value says otherwise
+fn toggle(props: Option<Node>, name: &str) -> bool {
+    props
+        .and_then(|p| child(p, name))
+        .is_some_and(|t| !matches!(attr(t, "val"), Some("0" | "false" | "off")))
+}
+
+fn paragraph_spans(paragraph: Node) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    for run in paragraph.descendants().filter(|n| is(*n, "r")) {
+        let props = child(run, "rPr");
+        let (bold, italic) = (toggle(props, "b"), toggle(props, "i"));
+        let mut text = String::new();
+        for part in run.children() {
+            match part.tag_name().name() {
+                "t" if part.is_element() => text.push_str(part.text().unwrap_or("")),
+                "tab" if part.is_element() => text.push('\t'),
+                "br" | "cr" if part.is_element() => text.push('\n'),
+                _ => {}
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+        // Merge runs with the same emphasis; Word splits runs freely
+        match spans.last_mut() {
+            Some(last) if last.bold == bold && last.italic == italic => last.text.push_str(&text),
+            _ => spans.push(Span { text, bold, italic }),
+        }
+    }
+    spans
+}
+
+fn heading_level(props: Option<Node>) -> Option<usize> {
+    let props = props?;
+    if let Some(level) = child(props, "outlineLvl").and_then(|o| attr(o, "val")) {
+        return level.parse::<usize>().ok().map(|l| (l + 1).min(6));
+    }
+    let style = child(props, "pStyle").and_then(|s| attr(s, "val"))?;
+    if style.eq_ignore_ascii_case("Title") {
+        return Some(1);
+    }
+    let level = style
+        .to_ascii_lowercase()
+        .strip_prefix("heading")?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(level.clamp(1, 6))
+}
+
+fn paragraph_block(paragraph: Node) -> Option<Block> {
+    let spans = paragraph_spans(paragraph);
+    if plain_text(&spans).trim().is_empty() {
+        return None;
+    }
+    let props = child(paragraph, "pPr");
+    if let Some(level) = heading_level(props) {
+        return Some(Block::Heading(level, spans));
+    }
+    if let Some(numbering) = props.and_then(|p| child(p, "numPr")) {
+        let depth = child(numbering, "ilvl")
+            .and_then(|l| attr(l, "val"))
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        return Some(Block::ListItem(depth, spans));
+    }
+    Some(Block::Paragraph(spans))
+}
+
+fn table_block(table: Node) -> Block {
+    let rows = table
+        .children()
+        .filter(|n| is(*n, "tr"))
+        .map(|row| {
+            row.children()
+                .filter(|n| is(*n, "tc"))
+                .map(|cell| {
+                    cell.children()
+                        .filter(|n| is(*n, "p"))
+                        .map(|p| plain_text(&paragraph_spans(p)))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                })
+                .collect()
+        })
+        .collect();
+    Block::Table(rows)
+}
+
+/// Each bookmark's first and last block, inclusive
+type Bookmarks = HashMap<String, (usize, usize)>;
+
+/// Blocks of the document body, with each bookmark's block range
+fn docx_body(xml: &str) -> Result<(Vec<Block>, Bookmarks)> {
+    let doc = Document::parse(xml).context("Invalid word/document.xml")?;
+    let body = doc
+        .descendants()
+        .find(|n| is(*n, "body"))
+        .ok_or_else(|| anyhow!("word/document.xml has no body"))?;
+
+    let mut blocks = Vec::new();
+    let mut open: HashMap<String, (String, usize)> = HashMap::new();
+    let mut bookmarks = HashMap::new();
+
+    for node in body.children().filter(|n| n.is_element()) {
+        let block = match node.tag_name().name() {
+            "p" => paragraph_block(node),
+            "tbl" => Some(table_block(node)),
+            _ => None,
+        };
+
+        // Bookmarks can sit inside a block or between blocks
+        let index = blocks.len();
+        for mark in node.descendants() {
+            if is(mark, "bookmarkStart")
+                && let (Some(id), Some(name)) = (attr(mark, "id"), attr(mark, "name"))
+            {
+                open.insert(id.to_string(), (name.to_string(), index));
+            } else if is(mark, "bookmarkEnd")
+                && let Some((name, start)) = attr(mark, "id").and_then(|id| open.remove(id))
+            {
+                let end = if block.is_some() || index == 0 {
+                    index
+                } else {
+                    index - 1
+                };
+                bookmarks.insert(name, (start, end));
+            }
+        }
+
+        if let Some(block) = block {
+            blocks.push(block);
+        }
+    }
+
+    Ok((blocks, bookmarks))
+}
+
+fn docx_blocks(archive: &mut Archive, uri: &str, selector: Option<&str>) -> Result<Vec<Block>> {
+    let xml = require_part(archive, "word/document.xml", uri)?;
+    let (blocks, bookmarks) = docx_body(&xml)?;
+
+    let Some(selector) = selector else {
+        return Ok(blocks);
+    };
+
+    if let Some(name) = selector.strip_prefix("bookmark:") {
+        let (start, end) = bookmarks
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("No bookmark '{}' in {}", name, uri))?;
+        return Ok(blocks
+            .get(start..=end.min(blocks.len().saturating_sub(1)))
+            .unwrap_or_default()
+            .to_vec());
+    }
+
+    let title = selector.strip_prefix("heading:").ok_or_else(|| {
+        anyhow!(
+            "Word selectors are heading:TEXT or bookmark:NAME, got '{}' for {}",
+            selector,
+            uri
+        )
+    })?;
+    let (index, level) = blocks
+        .iter()
+        .enumerate()
+        .find_map(|(i, block)| match block {
+            Block::Heading(level, spans)
+                if plain_text(spans).trim().eq_ignore_ascii_case(title.trim()) =>
+            {
+                Some((i, *level))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("No heading '{}' in {}", title, uri))?;
+
+    // Everything up to the next heading at the same or a higher level
+    Ok(blocks[index + 1..]
+        .iter()
+        .take_while(|block| !matches!(block, Block::Heading(l, _) if *l <= level))
+        .cloned()
+        .collect())
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn spans_to_markdown(spans: &[Span]) -> String {
+    spans
+        .iter()
+        .map(|span| {
+            let text = escape_markdown(&span.text).replace('\n', "  \n");
+            // Emphasis markers must hug the text, so surrounding spaces stay outside
+            let trimmed = text.trim();
+            if trimmed.is_empty() || !(span.bold || span.italic) {
+                return text;
+            }
+            let marker = match (span.bold, span.italic) {
+                (true, true) => "***",
+                (true, false) => "**",
+                _ => "*",
+            };
+            let lead = &text[..text.len() - text.trim_start().len()];
+            let trail = &text[text.trim_end().len()..];
+            format!("{}{}{}{}{}", lead, marker, trimmed, marker, trail)
+        })
+        .collect()
+}
+
+fn spans_to_html(spans: &[Span]) -> String {
+    spans
+        .iter()
+        .map(|span| {
+            let mut text = escape_html(&span.text).replace('\n', "<br>");
+            if span.italic {
+                text = format!("<em>{}</em>", text);
+            }
+            if span.bold {
+                text = format!("<strong>{}</strong>", text);
+            }
+            text
+        })
+        .collect()
+}
+
+fn blocks_to_markdown(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    let mut previous_was_item = false;
+    for block in blocks {
+        let is_item = matches!(block, Block::ListItem(..));
+        if !out.is_empty() {
+            out.push_str(if is_item && previous_was_item {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+        match block {
+            Block::Heading(level, spans) => out.push_str(&format!(
+                "{} {}",
+                "#".repeat(*level),
+                spans_to_markdown(spans)
+            )),
+            Block::Paragraph(spans) => out.push_str(&spans_to_markdown(spans)),
+            Block::ListItem(depth, spans) => out.push_str(&format!(
+                "{}- {}",
+                "  ".repeat(*depth),
+                spans_to_markdown(spans)
+            )),
+            Block::Table(rows) => out.push_str(&table::to_markdown(rows)),
+        }
+        previous_was_item = is_item;
+    }
+    out
+}
+
+fn blocks_to_html(blocks: &[Block]) -> String {
+    let mut lines = Vec::new();
+    let mut in_list = false;
+    for block in blocks {
+        let is_item = matches!(block, Block::ListItem(..));
+        if in_list && !is_item {
+            lines.push("</ul>".to_string());
+        } else if !in_list && is_item {
+            lines.push("<ul>".to_string());
+        }
+        in_list = is_item;
+        match block {
+            Block::Heading(level, spans) => {
+                lines.push(format!("<h{0}>{1}</h{0}>", level, spans_to_html(spans)))
+            }
+            Block::Paragraph(spans) => lines.push(format!("<p>{}</p>", spans_to_html(spans))),
+            Block::ListItem(_, spans) => lines.push(format!("<li>{}</li>", spans_to_html(spans))),
+            Block::Table(rows) => lines.push(table::to_html(rows)),
+        }
+    }
+    if in_list {
+        lines.push("</ul>".to_string());
+    }
+    lines.join("\n")
+}
+
+// -----------------------------------------------------------------------------
+// Spreadsheets
+// -----------------------------------------------------------------------------
+
+/// Non-empty cells of a sheet, keyed by 1-based (row, column)
+type Cells = BTreeMap<(u32, u32), String>;
+
+/// A 1-based, inclusive rectangle of cells
+type Range = (u32, u32, u32, u32);
+
+/// The largest sheet Excel and LibreOffice allow: columns up to XFD, 1048576 rows
+const MAX_COLUMNS: u32 = 16_384;
+const MAX_ROWS: u32 = 1_048_576;
+
+/// The most cells one selection may render
+const MAX_CELLS: u64 = 1_000_000;
+
+/// Parse `B3` into (row, column)
+fn parse_cell(reference: &str) -> Option<(u32, u32)> {
+    let reference = reference.replace('$', "");
+    let split = reference.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let column = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .try_fold(0u32, |acc, b| {
+            acc.checked_mul(26)?.checked_add((b - b'A' + 1) as u32)
+        })?;
+    let row: u32 = digits.parse().ok()?;
+    if column > MAX_COLUMNS || row == 0 || row > MAX_ROWS {
+        return None;
+    }
+    Some((row, column))
+}
+
+/// Parse `A1:C3` (or a single cell) into a range
+fn parse_range(range: &str) -> Option<Range> {
+    let (start, end) = range.split_once(':').unwrap_or((range, range));
+    let (r1, c1) = parse_cell(start)?;
+    let (r2, c2) = parse_cell(end)?;
+    Some((r1.min(r2), c1.min(c2), r1.max(r2), c1.max(c2)))
+}
+
+/// Split `Sheet1!A1:C3` or `'My Sheet'!A1` into sheet name and range text
+fn split_sheet(selector: &str) -> Option<(String, &str)> {
+    let (sheet, range) = selector.rsplit_once('!')?;
+    let sheet = sheet
+        .strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .map(|s| s.replace("''", "'"))
+        .unwrap_or_else(|| sheet.to_string());
+    Some((sheet, range))
+}
+
+/// Cells in the range as rows; without a range, the sheet's used area from A1
+/// A range is first clipped to the used area, so only cells that exist are laid out.
+fn grid(cells: &Cells, range: Option<Range>, uri: &str) -> Result<Vec<Vec<String>>> {
+    let rows = cells.keys().map(|(r, _)| *r).max().unwrap_or(0);
+    let columns = cells.keys().map(|(_, c)| *c).max().unwrap_or(0);
+    let (r1, c1, r2, c2) = range.unwrap_or((1, 1, rows, columns));
+    let (r2, c2) = (r2.min(rows), c2.min(columns));
+    if r1 > r2 || c1 > c2 {
+        return Ok(Vec::new());
+    }
+
+    let size = u64::from(r2 - r1 + 1) * u64::from(c2 - c1 + 1);
+    if size > MAX_CELLS {
+        return Err(anyhow!(
+            "The selected range in {} spans {} cells; select at most {}",
+            uri,
+            size,
+            MAX_CELLS
+        ));
+    }
+    Ok((r1..=r2)
+        .map(|r| {
+            (c1..=c2)
+                .map(|c| cells.get(&(r, c)).cloned().unwrap_or_default())
+                .collect()
+        })
+        .collect())
+}
+
+/// What a spreadsheet selector asks for, once named ranges are resolved
+struct Selection {
+    sheet: Option<String>,
+    range: Option<Range>,
+}
+
+/// Interpret a selector against the sheet names and named ranges
+/// `named` maps a range name to its `Sheet!A1:B2` form.
+fn select(
+    selector: Option<&str>,
+    sheets: &[String],
+    named: &HashMap<String, String>,
+    uri: &str,
+) -> Result<Selection> {
+    let Some(selector) = selector else {
+        return Ok(Selection {
+            sheet: None,
+            range: None,
+        });
+    };
+
+    if let Some((sheet, range)) = split_sheet(selector) {
+        let range = parse_range(range)
+            .ok_or_else(|| anyhow!("Invalid cell range '{}' in {}", range, uri))?;
+        return Ok(Selection {
+            sheet: Some(sheet),
+            range: Some(range),
+        });
+    }
+    if let Some(range) = parse_range(selector) {
+        return Ok(Selection {
+            sheet: None,
+            range: Some(range),
+        });
+    }
+    if sheets.iter().any(|s| s == selector) {
+        return Ok(Selection {
+            sheet: Some(selector.to_string()),
+            range: None,
+        });
+    }
+    if let Some(target) = named.get(selector) {
+        let (sheet, range) = split_sheet(target)
+            .ok_or_else(|| anyhow!("Named range '{}' is not a cell range in {}", selector, uri))?;
+        let range = parse_range(range)
+            .ok_or_else(|| anyhow!("Named range '{}' is not a cell range in {}", selector, uri))?;
+        return Ok(Selection {
+            sheet: Some(sheet),
+            range: Some(range),
+        });
+    }
+
+    Err(anyhow!(
+        "No sheet, cell range or named range '{}' in {}",
+        selector,
+        uri
+    ))
+}
+
+fn sheet_index(sheets: &[String], sheet: Option<&str>, uri: &str) -> Result<usize> {
+    match sheet {
+        None if !sheets.is_empty() => Ok(0),
+        None => Err(anyhow!("{} has no sheets", uri)),
+        Some(name) => sheets
+            .iter()
+            .position(|s| s == name)
+            .ok_or_else(|| anyhow!("No sheet '{}' in {}", name, uri)),
+    }
+}
+
+fn non_empty(rows: Vec<Vec<String>>, uri: &str) -> Result<Vec<Vec<String>>> {
+    if rows.iter().all(|r| r.iter().all(|c| c.is_empty())) {
+        return Err(anyhow!("The selected cells in {} are empty", uri));
+    }
+    Ok(rows)
+}
+
+fn xlsx_range(
+    archive: &mut Archive,
+    uri: &str,
+    selector: Option<&str>,
+) -> Result<Vec<Vec<String>>> {
+    let workbook_xml = require_part(archive, "xl/workbook.xml", uri)?;
+    let workbook = Document::parse(&workbook_xml).context("Invalid xl/workbook.xml")?;
+
+    let mut sheets = Vec::new();
+    let mut sheet_ids = Vec::new();
+    for sheet in workbook.descendants().filter(|n| is(*n, "sheet")) {
+        sheets.push(attr(sheet, "name").unwrap_or_default().to_string());
+        sheet_ids.push(attr(sheet, "id").unwrap_or_default().to_string());
+    }
+    let named: HashMap<String, String> = workbook
+        .descendants()
+        .filter(|n| is(*n, "definedName"))
+        .filter_map(|n| Some((attr(n, "name")?.to_string(), n.text()?.to_string())))
+        .collect();
+
+    let selection = select(selector, &sheets, &named, uri)?;
+    let index = sheet_index(&sheets, selection.sheet.as_deref(), uri)?;
+
+    // Map the sheet's relationship id to its part
+    let rels_xml = require_part(archive, "xl/_rels/workbook.xml.rels", uri)?;
+    let rels = Document::parse(&rels_xml).context("Invalid xl/_rels/workbook.xml.rels")?;
+    let target = rels
+        .descendants()
+        .filter(|n| is(*n, "Relationship"))
+        .find(|n| attr(*n, "Id") == Some(sheet_ids[index].as_str()))
+        .and_then(|n| attr(n, "Target"))
+        .ok_or_else(|| anyhow!("Sheet '{}' has no part in {}", sheets[index], uri))?;
+    let part = match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("xl/{}", target),
+    };
+
+    let shared_strings = match read_part(archive, "xl/sharedStrings.xml")? {
+        Some(xml) => {
+            let doc = Document::parse(&xml).context("Invalid xl/sharedStrings.xml")?;
+            doc.root_element()
+                .children()
+                .filter(|n| is(*n, "si"))
+                .map(|si| {
+                    si.descendants()
+                        .filter(|n| is(*n, "t") && !n.ancestors().any(|a| is(a, "rPh")))
+                        .filter_map(|t| t.text())
+                        .collect::<String>()
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    let sheet_xml = require_part(archive, &part, uri)?;
+    let sheet = Document::parse(&sheet_xml).with_context(|| format!("Invalid {}", part))?;
+    let mut cells = Cells::new();
+    for cell in sheet.descendants().filter(|n| is(*n, "c")) {
+        let Some(position) = attr(cell, "r").and_then(parse_cell) else {
+            continue;
+        };
+        let raw = child(cell, "v").and_then(|v| v.text()).unwrap_or("");
+        let value = match attr(cell, "t") {
+            Some("s") => raw
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| shared_strings.get(i).cloned())
+                .unwrap_or_default(),
+            Some("inlineStr") => child(cell, "is")
+                .map(|is_node| {
+                    is_node
+                        .descendants()
+                        .filter(|n| is(*n, "t"))
+                        .filter_map(|t| t.text())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Some("b") => if raw == "1" { "TRUE" } else { "FALSE" }.to_string(),
+            _ => raw.to_string(),
+        };
+        if !value.is_empty() {
+            cells.insert(position, value);
+        }
+    }
+
+    non_empty(grid(&cells, selection.range, uri)?, uri)
+}
+
+/// Convert an ODF range address (`$Sheet1.$A$1:.$C$3`) to `Sheet1!A1:C3`
+fn odf_address(address: &str) -> Option<String> {
+    let re = Regex::new(
+        r"^\$?'?([^.']+)'?\.\$?([A-Z]+)\$?(\d+)(?::(?:\$?'?[^.']*'?)?\.\$?([A-Z]+)\$?(\d+))?$",
+    )
+    .ok()?;
+    let caps = re.captures(address)?;
+    let start = format!("{}{}", &caps[2], &caps[3]);
+    let end = match (caps.get(4), caps.get(5)) {
+        (Some(c), Some(r)) => format!("{}{}", c.as_str(), r.as_str()),
+        _ => start.clone(),
+    };
+    Some(format!(
+        "'{}'!{}:{}",
+        caps[1].replace('\'', "''"),
+        start,
+        end
+    ))
+}
+
+fn repeat(node: Node, name: &str) -> u32 {
+    attr(node, name).and_then(|v| v.parse().ok()).unwrap_or(1)
+}
+
+fn ods_range(archive: &mut Archive, uri: &str, selector: Option<&str>) -> Result<Vec<Vec<String>>> {
+    let xml = require_part(archive, "content.xml", uri)?;
+    let doc = Document::parse(&xml).context("Invalid content.xml")?;
+
+    let tables: Vec<Node> = doc
+        .descendants()
+        .filter(|n| {
+            is(*n, "table")
+                && n.tag_name()
+                    .namespace()
+                    .is_some_and(|ns| ns.contains("table"))
+        })
+        .collect();
+    let sheets: Vec<String> = tables
+        .iter()
+        .map(|t| attr(*t, "name").unwrap_or_default().to_string())
+        .collect();
+    let named: HashMap<String, String> = doc
+        .descendants()
+        .filter(|n| is(*n, "named-range"))
+        .filter_map(|n| {
+            Some((
+                attr(n, "name")?.to_string(),
+                odf_address(attr(n, "cell-range-address")?)?,
+            ))
+        })
+        .collect();
+
+    let selection = select(selector, &sheets, &named, uri)?;
+    let index = sheet_index(&sheets, selection.sheet.as_deref(), uri)?;
+
+    let mut cells = Cells::new();
+    let mut row_number = 0u32;
+    for row in tables[index].descendants().filter(|n| is(*n, "table-row")) {
+        let rows = repeat(row, "number-rows-repeated");
+        let mut values = Vec::new();
+        let mut column = 0u32;
+        for cell in row
+            .children()
+            .filter(|n| is(*n, "table-cell") || is(*n, "covered-table-cell"))
+        {
+            let columns = repeat(cell, "number-columns-repeated");
+            let text = cell
+                .children()
+                .filter(|n| is(*n, "p"))
+                .map(|p| {
+                    p.descendants()
+                        .filter(|t| t.is_text())
+                        .filter_map(|t| t.text())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            // Repeat counts are untrusted, so nothing is placed past the sheet's edge
+            if !text.is_empty() {
+                for c in 1..=columns.min(MAX_COLUMNS.saturating_sub(column)) {
+                    values.push((column + c, text.clone()));
+                }
+            }
+            column = column.saturating_add(columns);
+        }
+        // Repeated rows are usually the empty filler at the end of a sheet
+        if !values.is_empty() {
+            let rows = rows.min(MAX_ROWS.saturating_sub(row_number));
+            if cells.len() as u64 + values.len() as u64 * u64::from(rows) > MAX_CELLS {
+                return Err(anyhow!("{} has more than {} filled cells", uri, MAX_CELLS));
+            }
+            for r in 1..=rows {
+                for (c, text) in &values {
+                    cells.insert((row_number + r, *c), text.clone());
+                }
+            }
+        }
+        row_number = row_number.saturating_add(rows);
+    }
+
+    non_empty(grid(&cells, selection.range, uri)?, uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_bytes(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+    fn docx() -> Vec<u8> {
+        let body = r#"
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Spec</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Goals</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Ship a </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>fast</w:t></w:r><w:r><w:t xml:space="preserve"> importer.</w:t></w:r></w:p>
+<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:bookmarkStart w:id="0" w:name="scope"/><w:r><w:t>CSV &lt;input&gt;</w:t></w:r></w:p>
+<w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:rPr><w:i/></w:rPr><w:t>with headers</w:t></w:r><w:bookmarkEnd w:id="0"/></w:p>
+<w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Non-goals</w:t></w:r></w:p>
+<w:p><w:r><w:t>Excel.</w:t></w:r></w:p>
+"#;
+        let document = format!(r#"<w:document {W}><w:body>{body}</w:body></w:document>"#);
+        zip_bytes(&[("word/document.xml", &document)])
+    }
+
+    #[test]
+    fn test_docx_heading_markdown() {
+        let result = extract(
+            &docx(),
+            "spec.docx",
+            Some("heading:Goals"),
+            Format::Markdown,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            "Ship a **fast** importer.\n\n- CSV \\<input>\n  - *with headers*"
+        );
+    }
+
+    #[test]
+    fn test_docx_bookmark_html() {
+        let result = extract(&docx(), "spec.docx", Some("bookmark:scope"), Format::Html).unwrap();
+        assert_eq!(
+            result,
+            "<ul>\n<li>CSV &lt;input&gt;</li>\n<li><em>with headers</em></li>\n</ul>"
+        );
+        assert!(
+            extract(
+                &docx(),
+                "spec.docx",
+                Some("heading:Missing"),
+                Format::Markdown
+            )
+            .is_err()
+        );
+        assert!(extract(&docx(), "spec.docx", Some("Goals"), Format::Markdown).is_err());
+    }
+
+    fn xlsx() -> Vec<u8> {
+        let ns = r#"xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships""#;
+        let workbook = format!(
+            r#"<workbook {ns}><sheets><sheet name="Plans" sheetId="1" r:id="rId1"/></sheets>
+<definedNames><definedName name="Prices">Plans!$A$1:$B$3</definedName></definedNames></workbook>"#
+        );
+        let rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="worksheet" Target="worksheets/sheet1.xml"/></Relationships>"#;
+        let strings = format!(
+            r#"<sst {ns}><si><t>Plan</t></si><si><t>Price</t></si><si><r><t>Pro</t></r><r><t>+</t></r></si></sst>"#
+        );
+        let sheet = format!(
+            r#"<worksheet {ns}><sheetData>
+<row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c></row>
+<row r="2"><c r="A2" t="inlineStr"><is><t>Free</t></is></c><c r="B2"><v>0</v></c></row>
+<row r="3"><c r="A3" t="s"><v>2</v></c><c r="B3"><v>12.5</v></c><c r="C3" t="b"><v>1</v></c></row>
+</sheetData></worksheet>"#
+        );
+        zip_bytes(&[
+            ("xl/workbook.xml", &workbook),
+            ("xl/_rels/workbook.xml.rels", rels),
+            ("xl/sharedStrings.xml", &strings),
+            ("xl/worksheets/sheet1.xml", &sheet),
+        ])
+    }
+
+    #[test]
+    fn test_xlsx_ranges() {
+        let bytes = xlsx();
+        let expected = "| Plan | Price |\n| --- | --- |\n| Free | 0 |\n| Pro+ | 12.5 |";
+        assert_eq!(
+            extract(&bytes, "p.xlsx", Some("Plans!A1:B3"), Format::Markdown).unwrap(),
+            expected
+        );
+        assert_eq!(
+            extract(&bytes, "p.xlsx", Some("Prices"), Format::Markdown).unwrap(),
+            expected
+        );
+        assert_eq!(
+            extract(&bytes, "p.xlsx", Some("B3"), Format::Markdown).unwrap(),
+            "12.5"
+        );
+        assert!(
+            extract(&bytes, "p.xlsx", Some("Plans"), Format::Markdown)
+                .unwrap()
+                .contains("| Pro+ | 12.5 | TRUE |")
+        );
+        assert!(extract(&bytes, "p.xlsx", Some("Other!A1"), Format::Markdown).is_err());
+
+        // The whole sheet's worth of range is clipped to the cells in use
+        assert_eq!(
+            extract(
+                &bytes,
+                "p.xlsx",
+                Some("Plans!A1:XFD1048576"),
+                Format::Markdown
+            )
+            .unwrap(),
+            extract(&bytes, "p.xlsx", Some("Plans"), Format::Markdown).unwrap()
+        );
+        let err = extract(&bytes, "p.xlsx", Some("Plans!AAAAAAAA1"), Format::Markdown);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Invalid cell range 'AAAAAAAA1' in p.xlsx"
+        );
+    }
+
+    #[test]
+    fn test_ods_ranges() {
+        let content = r#"<office:document-content
+ xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+ xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0"
+ xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:spreadsheet>
+<table:table table:name="Plans">
+<table:table-row><table:table-cell><text:p>Plan</text:p></table:table-cell><table:table-cell><text:p>Price</text:p></table:table-cell><table:table-cell table:number-columns-repeated="1020"/></table:table-row>
+<table:table-row><table:table-cell><text:p>Pro</text:p></table:table-cell><table:table-cell><text:p>$10</text:p></table:table-cell></table:table-row>
+<table:table-row table:number-rows-repeated="1048574"><table:table-cell table:number-columns-repeated="1024"/></table:table-row>
+</table:table>
+<table:named-expressions><table:named-range table:name="Prices" table:cell-range-address="$Plans.$A$1:.$B$2"/></table:named-expressions>
+</office:spreadsheet></office:body></office:document-content>"#;
+        let bytes = zip_bytes(&[("content.xml", content)]);
+        let expected = "| Plan | Price |\n| --- | --- |\n| Pro | $10 |";
+        assert_eq!(
+            extract(&bytes, "p.ods", None, Format::Markdown).unwrap(),
+            expected
+        );
+        assert_eq!(
+            extract(&bytes, "p.ods", Some("Prices"), Format::Markdown).unwrap(),
+            expected
+        );
+        assert_eq!(
+            extract(&bytes, "p.ods", Some("Plans!B2"), Format::Html).unwrap(),
+            "$10"
+        );
+    }
+
+    #[test]
+    fn test_ods_repeats_are_bounded() {
+        let content = r#"<office:document-content
+ xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0"
+ xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0"
+ xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0">
+<office:body><office:spreadsheet>
+<table:table table:name="Huge">
+<table:table-row table:number-rows-repeated="4294967295"><table:table-cell table:number-columns-repeated="4294967295"><text:p>x</text:p></table:table-cell></table:table-row>
+</table:table>
+</office:spreadsheet></office:body></office:document-content>"#;
+        let bytes = zip_bytes(&[("content.xml", content)]);
+        let err = extract(&bytes, "h.ods", None, Format::Markdown).unwrap_err();
+        assert_eq!(err.to_string(), "h.ods has more than 1000000 filled cells");
+    }
+
+    #[test]
+    fn test_parse_cell() {
+        assert_eq!(parse_cell("A1"), Some((1, 1)));
+        assert_eq!(parse_cell("$AB$12"), Some((12, 28)));
+        assert_eq!(parse_cell("12"), None);
+        assert_eq!(parse_cell("XFD1048576"), Some((1_048_576, 16_384)));
+        assert_eq!(parse_cell("XFE1"), None);
+        assert_eq!(parse_cell("A1048577"), None);
+        assert_eq!(parse_cell("A0"), None);
+        // Would overflow a u32 column number
+        assert_eq!(parse_cell("AAAAAAAA1"), None);
+        assert_eq!(odf_address("$Plans.$A$1:.$B$2").unwrap(), "'Plans'!A1:B2");
+    }
+}
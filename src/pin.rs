@@ -0,0 +1,141 @@
+//! `liaison pin`: fill in and refresh `?sha256=` integrity pins
+//!
+//! Every reference that already carries a `sha256=` option (an empty
+//! `sha256=` works as a placeholder) gets the digest of its current content.
+//! With `--add`, remote references without a pin get one too.
+
+use anyhow::{Context, Result};
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::http::sha256_hex;
+use crate::policy;
+use crate::processor::FileChange;
+use crate::resolver::{Reference, Resolver, is_remote_uri, split_reference};
+
+/// Pin references in the given files, returning the changed files and the number of pins written
+pub fn pin_files(
+    repo_root: &Path,
+    config: &Config,
+    files: &[PathBuf],
+    add: bool,
+) -> Result<(Vec<FileChange>, usize)> {
+    let mut resolver = Resolver::new(repo_root.to_path_buf(), config.clone());
+    let pattern = Regex::new(r#"transclude="([^"]+)""#).unwrap();
+    let mut changes = Vec::new();
+    let mut pinned = 0;
+
+    for file in files {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read file: {:?}", file))?;
+        let file_str = file
+            .strip_prefix(repo_root)
+            .unwrap_or(file)
+            .display()
+            .to_string();
+
+        // Only the markers and start tags the host parsers find are pinned, so
+        // generated content and prose that mentions `transclude=` stay as they are
+        let found = policy::references_in(&content, &file.to_string_lossy())
+            .with_context(|| format!("Failed to parse {:?}", file))?;
+
+        let mut error = None;
+        let new_content = pattern.replace_all(&content, |caps: &Captures| {
+            let whole = caps[0].to_string();
+            let start = caps.get(0).unwrap().start();
+            if error.is_some() || !found.iter().any(|found| found.span.contains(&start)) {
+                return whole;
+            }
+            match pin_reference(&caps[1], &mut resolver, &file_str, add) {
+                Ok(Some(pinned_reference)) => {
+                    pinned += 1;
+                    format!("transclude=\"{}\"", pinned_reference)
+                }
+                Ok(None) => whole,
+                Err(e) => {
+                    error = Some(e.context(format!("Failed to pin {:?}", file)));
+                    whole
+                }
+            }
+        });
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        if new_content != content {
+            changes.push(FileChange {
+                path: file.clone(),
+                new_content: new_content.into_owned(),
+            });
+        }
+    }
+
+    Ok((changes, pinned))
+}
+
+/// The reference text with its pin set to the current digest, or None to leave it alone
+fn pin_reference(
+    text: &str,
+    resolver: &mut Resolver,
+    current_file: &str,
+    add: bool,
+) -> Result<Option<String>> {
+    let mut reference = Reference::parse(text)?;
+    let has_pin = reference.options.remove("sha256").is_some();
+    let wants_pin = has_pin || (add && is_remote_uri(&reference.uri));
+    if !wants_pin {
+        return Ok(None);
+    }
+
+    let (content, _) = resolver.resolve(&reference, Some(current_file))?;
+    let digest = sha256_hex(content.as_bytes());
+    Ok(Some(set_pin(text, &digest)))
+}
+
+/// Replace the `sha256=` value in a reference, or append one
+fn set_pin(text: &str, digest: &str) -> String {
+    let Some(params) = split_reference(text).2 else {
+        return format!("{}?sha256={}", text, digest);
+    };
+    let head = &text[..text.len() - params.len()];
+
+    let existing = Regex::new(r"(^|&)sha256=[0-9a-fA-F]*").unwrap();
+    if existing.is_match(params) {
+        let params = existing.replace(params, |caps: &Captures| {
+            format!("{}sha256={}", &caps[1], digest)
+        });
+        return format!("{}{}", head, params);
+    }
+    let separator = if params.is_empty() { "" } else { "&" };
+    format!("{}{}sha256={}", text, separator, digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_pin() {
+        assert_eq!(
+            set_pin("https://example.com/a.rs#demo", "ab12"),
+            "https://example.com/a.rs#demo?sha256=ab12"
+        );
+        assert_eq!(
+            set_pin("https://example.com/a.rs?linenos", "ab12"),
+            "https://example.com/a.rs?linenos&sha256=ab12"
+        );
+        assert_eq!(
+            set_pin("src/lib.rs#helper?sha256=&noindent", "ab12"),
+            "src/lib.rs#helper?sha256=ab12&noindent"
+        );
+        assert_eq!(
+            set_pin("src/lib.rs?sha256=0000", "ab12"),
+            "src/lib.rs?sha256=ab12"
+        );
+        assert_eq!(
+            set_pin("https://example.com/doc?v=2#intro", "ab12"),
+            "https://example.com/doc?v=2#intro?sha256=ab12"
+        );
+    }
+}
@@ -14,6 +14,14 @@ pub enum Block {
         start_line: usize,
         end_line: usize,
     },
+    /// Single-line form whose markers share a line with the surrounding text;
+    /// `start..end` is the byte range of the content between the markers
+    Inline {
+        reference: String,
+        line: usize,
+        start: usize,
+        end: usize,
+    },
 }
 
 pub struct PlaintextParser {
@@ -52,9 +60,22 @@ impl PlaintextParser {
         let id_pattern = self.build_pattern("liaison id=(\\S+)");
         let transclude_pattern = self.build_pattern("liaison transclude=\"([^\"]+)\"");
         let end_pattern = self.build_pattern("liaison end");
+        let inline_pattern = self.build_inline_pattern();
 
         let mut i = 0;
         while i < lines.len() {
+            if let Some(pattern) = &inline_pattern {
+                for caps in pattern.captures_iter(lines[i]) {
+                    let content = caps.get(2).unwrap();
+                    blocks.push(Block::Inline {
+                        reference: caps.get(1).unwrap().as_str().to_string(),
+                        line: i,
+                        start: content.start(),
+                        end: content.end(),
+                    });
+                }
+            }
+
             let line = lines[i].trim();
 
             if let Some(caps) = id_pattern.captures(line) {
@@ -86,7 +107,9 @@ impl PlaintextParser {
                         start_line: start,
                         end_line,
                     });
-                    i = end_line + 1;
+                    // An id block's lines are the host's own text, so keep scanning
+                    // inside it for inline markers and nested blocks
+                    i += 1;
                 } else {
                     return Err(anyhow!("Unclosed 'liaison id' block at line {}", i + 1));
                 }
@@ -134,6 +157,48 @@ impl PlaintextParser {
         Ok(blocks)
     }
 
+    /// Wrap text in this file type's comment syntax
+    pub fn comment(&self, text: &str) -> String {
+        match &self.comment_end {
+            Some(end) => format!("{} {} {}", self.comment_start, text, end),
+            None => format!("{} {}", self.comment_start, text),
+        }
+    }
+
+    /// Pattern for `<!-- liaison transclude="..." -->value<!-- liaison end -->` within a line
+    /// Only comment syntaxes with an end delimiter can close a marker mid-line.
+    fn build_inline_pattern(&self) -> Option<Regex> {
+        let end = self.comment_end.as_ref()?;
+        let start = regex::escape(&self.comment_start);
+        let end = regex::escape(end);
+        let pattern = format!(
+            r#"{start}\s+liaison transclude="([^"]+)"\s*{end}(.*?){start}\s+liaison end\s*{end}"#
+        );
+        Some(Regex::new(&pattern).unwrap())
+    }
+
+    /// Replace the content between a pair of inline markers
+    pub fn replace_inline(
+        &self,
+        content: &str,
+        line: usize,
+        start: usize,
+        end: usize,
+        new_content: &str,
+    ) -> String {
+        let mut output = String::new();
+        for (i, text) in content.split_inclusive('\n').enumerate() {
+            if i == line {
+                output.push_str(&text[..start]);
+                output.push_str(new_content);
+                output.push_str(&text[end..]);
+            } else {
+                output.push_str(text);
+            }
+        }
+        output
+    }
+
     fn build_pattern(&self, inner: &str) -> Regex {
         let pattern = if let Some(end) = &self.comment_end {
             format!(
@@ -216,7 +281,8 @@ fn normalize_indentation(lines: &[&str], marker_line: &str) -> Vec<String> {
         .collect()
 }
 
-pub fn extract_by_id(content: &str, uri: &str, id: &str) -> Result<String> {
+/// Returns the block's content and its 1-based source line range
+pub fn extract_by_id(content: &str, uri: &str, id: &str) -> Result<(String, (usize, usize))> {
     // Parse the content to find blocks
     let path = Path::new(uri);
     let parser = PlaintextParser::new(path);
@@ -237,13 +303,64 @@ pub fn extract_by_id(content: &str, uri: &str, id: &str) -> Result<String> {
 
             // Normalize indentation based on marker line's indentation
             let normalized = normalize_indentation(content_lines, marker_line);
-            return Ok(normalized.join("\n"));
+            // Content starts on the line after the marker; convert to 1-based
+            return Ok((normalized.join("\n"), (start_line + 2, end_line)));
         }
     }
 
     Err(anyhow!("No block with id '{}' found in {}", id, uri))
 }
 
+/// Parse a GitHub-style line selector: `L12` or `L12-L40` (1-based, inclusive)
+pub fn parse_line_range(selector: &str) -> Option<(usize, usize)> {
+    let re = Regex::new(r"^L(\d+)(?:-L?(\d+))?$").unwrap();
+    let caps = re.captures(selector)?;
+    let start: usize = caps[1].parse().ok()?;
+    let end: usize = match caps.get(2) {
+        Some(end) => end.as_str().parse().ok()?,
+        None => start,
+    };
+    Some((start, end))
+}
+
+/// Extract a 1-based, inclusive range of lines
+pub fn extract_lines(content: &str, uri: &str, start: usize, end: usize) -> Result<String> {
+    let count = content.lines().count();
+    if start == 0 || start > end || end > count {
+        return Err(anyhow!(
+            "Line range L{}-L{} is outside {} ({} lines)",
+            start,
+            end,
+            uri,
+            count
+        ));
+    }
+    Ok(content
+        .lines()
+        .skip(start - 1)
+        .take(end - start + 1)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Prefix each line with its original line number, starting at `first_line`
+pub fn number_lines(content: &str, first_line: usize) -> String {
+    let count = content.lines().count();
+    let width = (first_line + count.saturating_sub(1)).to_string().len();
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if line.is_empty() {
+                format!("{:>width$} |", first_line + i, width = width)
+            } else {
+                format!("{:>width$} | {}", first_line + i, line, width = width)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -295,7 +412,7 @@ old content
     // liaison end
 }"#;
 
-        let result = extract_by_id(content, "test.rs", "indented-code").unwrap();
+        let (result, _) = extract_by_id(content, "test.rs", "indented-code").unwrap();
 
         // The marker has 4 spaces, so all content should have that removed
         let expected = "let x = 5;\nif x > 0 {\n    println!(\"positive\");\n}";
@@ -312,11 +429,104 @@ old content
                       // liaison end
 }"#;
 
-        let result = extract_by_id(content, "test.rs", "code").unwrap();
+        let (result, lines) = extract_by_id(content, "test.rs", "code").unwrap();
+
+        // Content lines only, 1-based
+        assert_eq!(lines, (3, 4));
 
         // End marker's indentation (22 spaces) should not affect extraction
         // Only the start marker's indentation (4 spaces) matters
         let expected = "let x = 5;\nlet y = 10;";
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_inline_blocks() {
+        let content = "Install v<!-- liaison transclude=\"cargo:package.version\" -->0.1<!-- liaison end --> \
+                       or <!-- liaison transclude=\"git:short-sha\" --><!-- liaison end -->.\n";
+        let parser = PlaintextParser::new(Path::new("README.md"));
+        let blocks = parser.parse(content).unwrap();
+        assert_eq!(blocks.len(), 2);
+
+        let mut result = content.to_string();
+        for block in blocks.iter().rev() {
+            if let Block::Inline {
+                line, start, end, ..
+            } = block
+            {
+                result = parser.replace_inline(&result, *line, *start, *end, "X");
+            }
+        }
+        assert_eq!(
+            result,
+            "Install v<!-- liaison transclude=\"cargo:package.version\" -->X<!-- liaison end --> \
+             or <!-- liaison transclude=\"git:short-sha\" -->X<!-- liaison end -->.\n"
+        );
+
+        // Line comments can't be closed mid-line, so there is no inline form
+        let parser = PlaintextParser::new(Path::new("a.rs"));
+        assert!(
+            parser
+                .parse("// liaison transclude=\"x\" -->1<!-- liaison end")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_markers_inside_id_block() {
+        let content = "<!-- liaison id=install -->\n\
+                       Version <!-- liaison transclude=\"cargo:package.version\" -->1<!-- liaison end -->.\n\
+                       <!-- liaison transclude=\"notes.md\" -->\n\
+                       Inline <!-- liaison transclude=\"x\" -->generated<!-- liaison end -->\n\
+                       <!-- liaison end -->\n\
+                       <!-- liaison end -->\n";
+        let parser = PlaintextParser::new(Path::new("README.md"));
+        let blocks = parser.parse(content).unwrap();
+
+        assert_eq!(blocks.len(), 3);
+        assert!(matches!(
+            &blocks[0],
+            Block::Id {
+                start_line: 0,
+                end_line: 5,
+                ..
+            }
+        ));
+        assert!(matches!(&blocks[1], Block::Inline { line: 1, .. }));
+        // Generated content inside a transclude block is not scanned
+        assert!(matches!(
+            &blocks[2],
+            Block::Transclude {
+                start_line: 2,
+                end_line: 4,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_line_range() {
+        assert_eq!(parse_line_range("L100-L140"), Some((100, 140)));
+        assert_eq!(parse_line_range("L7"), Some((7, 7)));
+        assert_eq!(parse_line_range("L3-5"), Some((3, 5)));
+        assert_eq!(parse_line_range("Lucky"), None);
+        assert_eq!(extract_lines("a\nb\nc", "x.rs", 2, 3).unwrap(), "b\nc");
+        assert!(extract_lines("a", "x.rs", 2, 2).is_err());
+    }
+
+    #[test]
+    fn test_number_lines() {
+        let result = number_lines("a\n\nb", 9);
+        assert_eq!(result, " 9 | a\n10 |\n11 | b");
+    }
+
+    #[test]
+    fn test_comment_syntax() {
+        assert_eq!(PlaintextParser::new(Path::new("a.rs")).comment("x"), "// x");
+        assert_eq!(
+            PlaintextParser::new(Path::new("a.md")).comment("x"),
+            "<!-- x -->"
+        );
+    }
 }
@@ -0,0 +1,221 @@
+//! Network policy: which remote sources may be fetched
+//!
+//! Every file's references are checked before anything is fetched, so a
+//! disallowed URL is reported with the file and line that names it instead of
+//! surfacing halfway through a run.
+
+use anyhow::{Context, Result, anyhow};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::config::NetworkConfig;
+use crate::html;
+use crate::plaintext::{Block, PlaintextParser};
+use crate::resolver::{Reference, is_html_uri, is_markdown_uri, is_remote_uri, uri_path};
+
+/// A reference named in a file
+#[derive(Debug, PartialEq)]
+pub struct FoundReference {
+    /// The reference as written
+    pub text: String,
+    /// 1-based line of its marker or element
+    pub line: usize,
+    /// Byte range of the marker's line or the element's start tag, which holds the text
+    pub span: Range<usize>,
+}
+
+/// The references liaison expands in a file at `path`, in source order
+/// Found with the same parsers the processor uses: `transclude` attributes in HTML
+/// and Markdown, and comment and inline markers everywhere. Text inside a
+/// transclude block is generated, so it is not scanned.
+pub fn references_in(content: &str, path: &str) -> Result<Vec<FoundReference>> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_span = |line: usize| {
+        line_starts[line]..line_starts.get(line + 1).copied().unwrap_or(content.len())
+    };
+
+    let mut found = Vec::new();
+    if is_html_uri(path) || is_markdown_uri(path) {
+        for block in html::find_transclude_blocks(content)? {
+            found.push(FoundReference {
+                text: block.reference,
+                line: line_starts.partition_point(|&start| start <= block.start_pos),
+                span: block.start_pos..block.end_pos,
+            });
+        }
+    }
+
+    let parser = PlaintextParser::new(Path::new(uri_path(path).as_ref()));
+    for block in parser.parse(content)? {
+        let (text, line) = match block {
+            Block::Transclude {
+                reference,
+                start_line,
+                ..
+            } => (reference, start_line),
+            Block::Inline {
+                reference, line, ..
+            } => (reference, line),
+            Block::Id { .. } => continue,
+        };
+        found.push(FoundReference {
+            text,
+            line: line + 1,
+            span: line_span(line),
+        });
+    }
+
+    found.sort_by_key(|reference| reference.span.start);
+    Ok(found)
+}
+
+/// Why a URL isn't allowed, or Ok if it is
+pub fn check_url(url: &str, policy: &NetworkConfig) -> std::result::Result<(), String> {
+    if url.starts_with("http://") && !policy.allow_http {
+        return Err("plain http:// is not allowed (network.allow_http = false)".to_string());
+    }
+    let Some(allow) = &policy.allow else {
+        return Ok(());
+    };
+
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default();
+    let allowed = allow.iter().any(|entry| {
+        if entry.contains("://") {
+            url.starts_with(entry.as_str())
+        } else if let Some(suffix) = entry.strip_prefix("*.") {
+            host.ends_with(&format!(".{}", suffix))
+        } else {
+            host == *entry
+        }
+    });
+    if allowed {
+        Ok(())
+    } else {
+        Err(format!("{} is not in network.allow", host))
+    }
+}
+
+/// Check every remote reference in the files, reporting all violations at once
+pub fn check_files(repo_root: &Path, files: &[PathBuf], policy: &NetworkConfig) -> Result<()> {
+    let mut violations = Vec::new();
+    for file in files {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read file: {:?}", file))?;
+        let shown = file.strip_prefix(repo_root).unwrap_or(file).display();
+        let found = references_in(&content, &file.to_string_lossy())
+            .with_context(|| format!("Failed to parse {:?}", file))?;
+        for FoundReference { text, line, .. } in found {
+            let Ok(reference) = Reference::parse(&text) else {
+                continue;
+            };
+            if !is_remote_uri(&reference.uri) {
+                continue;
+            }
+            if let Err(reason) = check_url(&reference.uri, policy) {
+                violations.push(format!(
+                    "{}:{}: {} ({})",
+                    shown,
+                    line,
+                    reference.display_uri(),
+                    reason
+                ));
+            }
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Network policy violations:\n  {}",
+            violations.join("\n  ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_url() {
+        let policy = NetworkConfig {
+            allow: Some(vec![
+                "raw.githubusercontent.com".to_string(),
+                "*.example.org".to_string(),
+                "https://example.com/docs/".to_string(),
+            ]),
+            allow_http: false,
+            nested: true,
+        };
+        assert!(check_url("https://raw.githubusercontent.com/a/b/main/x.rs", &policy).is_ok());
+        assert!(check_url("https://docs.example.org/a.md", &policy).is_ok());
+        assert!(check_url("https://example.com/docs/a.md", &policy).is_ok());
+        assert!(check_url("https://example.com/private/a.md", &policy).is_err());
+        assert!(check_url("https://example.org.evil.net/a.md", &policy).is_err());
+        assert!(
+            check_url("http://docs.example.org/a.md", &policy)
+                .unwrap_err()
+                .contains("allow_http")
+        );
+        assert!(check_url("https://anything.net/a", &NetworkConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_references_in() {
+        let content = "# Title\n\
+                       <!-- liaison transclude=\"a.md\" -->\n\
+                       Generated text naming transclude=\"ignored.md\"\n\
+                       <!-- liaison end -->\n\
+                       <div\n  transclude=\"https://x.org/b.html\"></div>\n\
+                       v<!-- liaison transclude=\"cargo:package.version\" -->1<!-- liaison end -->\n\
+                       Prose that mentions transclude=\"c.md\".\n";
+        let found: Vec<(usize, String)> = references_in(content, "guide.md")
+            .unwrap()
+            .into_iter()
+            .map(|found| (found.line, found.text))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, "a.md".to_string()),
+                (5, "https://x.org/b.html".to_string()),
+                (7, "cargo:package.version".to_string()),
+            ]
+        );
+
+        // Elements only count in HTML and Markdown hosts
+        let found = references_in("<div transclude=\"a.html\"></div>\n", "notes.rs").unwrap();
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn test_check_files_reports_file_and_line() {
+        let dir = std::env::temp_dir().join(format!("liaison-policy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("guide.md");
+        std::fs::write(
+            &file,
+            "# Guide\n\n<!-- liaison transclude=\"https://evil.example/x.md#a\" -->\n<!-- liaison end -->\n\
+             <!-- liaison transclude=\"local.md\" -->\n<!-- liaison end -->\n",
+        )
+        .unwrap();
+        let policy = NetworkConfig {
+            allow: Some(vec!["docs.example.com".to_string()]),
+            ..NetworkConfig::default()
+        };
+
+        let err = check_files(&dir, &[file], &policy).unwrap_err().to_string();
+        assert!(err.contains(
+            "guide.md:3: https://evil.example/x.md (evil.example is not in network.allow)"
+        ));
+        assert_eq!(err.lines().count(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+//! Fetching remote sources concurrently, ahead of processing
+//!
+//! The walk over files resolves one reference at a time, so on its own it
+//! would fetch every remote source in turn. A discovery pass first collects the
+//! remote references named in the files and fetches them on `http.parallelism`
+//! threads, within each host's rate limit; the walk then picks the bodies up as
+//! it reaches them. Nothing is written until the walk is done, and a failed
+//! fetch is only reported when the walk needs it, so a run still succeeds or
+//! fails as a whole just as it would without prefetching.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::http::HttpCache;
+use crate::policy::references_in;
+use crate::resolver::{Reference, is_remote_uri};
+
+/// One URL to fetch
+pub struct Request {
+    pub url: String,
+    pub ttl: u64,
+    pub refresh: bool,
+}
+
+/// The outcome of a prefetch, kept until the walk reaches the reference
+pub struct Fetched {
+    pub body: Result<String, String>,
+    pub warnings: Vec<String>,
+}
+
+/// Remote references named directly in the files
+/// Unreadable or malformed files and references are left for the walk to report.
+pub fn discover(files: &[PathBuf]) -> Vec<Reference> {
+    files
+        .iter()
+        .filter_map(|file| {
+            let content = std::fs::read_to_string(file).ok()?;
+            references_in(&content, &file.to_string_lossy()).ok()
+        })
+        .flat_map(|found| {
+            found
+                .into_iter()
+                .filter_map(|found| Reference::parse(&found.text).ok())
+                .filter(|reference| is_remote_uri(&reference.uri))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Fetch every request, at most `parallelism` at a time
+pub fn fetch_all(
+    http: &HttpCache,
+    requests: Vec<Request>,
+    parallelism: usize,
+) -> HashMap<String, Fetched> {
+    let workers = parallelism.max(1).min(requests.len());
+    let queue = Mutex::new(requests.into_iter());
+    let results = Mutex::new(HashMap::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    // Take the next request without holding the queue during the fetch
+                    let next = queue.lock().unwrap().next();
+                    let Some(request) = next else {
+                        break;
+                    };
+                    let mut warnings = Vec::new();
+                    let body = http
+                        .fetch(&request.url, request.ttl, request.refresh, &mut warnings)
+                        .map_err(|e| format!("{:#}", e));
+                    results
+                        .lock()
+                        .unwrap()
+                        .insert(request.url, Fetched { body, warnings });
+                }
+            });
+        }
+    });
+
+    results.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FetchMode, HttpConfig, NetworkConfig};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    /// Stand-in server that answers each path with its own name after a delay
+    /// Returns the base URL, the most requests it had in flight at once, and
+    /// when each request arrived.
+    fn serve(delay: Duration) -> (String, Arc<AtomicUsize>, Arc<Mutex<Vec<Instant>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+
+        let (peak_seen, arrivals_seen) = (peak.clone(), arrivals.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let (in_flight, peak, arrivals) =
+                    (in_flight.clone(), peak.clone(), arrivals.clone());
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                            break;
+                        }
+                    }
+                    arrivals.lock().unwrap().push(Instant::now());
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(delay);
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+                    let status = if path.contains("missing") {
+                        "404 Not Found"
+                    } else {
+                        "200 OK"
+                    };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        path.len(),
+                        path
+                    );
+                    let _ = stream.write_all(response.as_bytes());
+                });
+            }
+        });
+        (base, peak_seen, arrivals_seen)
+    }
+
+    fn cache(name: &str, config: HttpConfig) -> HttpCache {
+        let dir =
+            std::env::temp_dir().join(format!("liaison-prefetch-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        HttpCache::new(dir, FetchMode::Normal, config, NetworkConfig::default())
+    }
+
+    fn requests(base: &str, paths: &[&str]) -> Vec<Request> {
+        paths
+            .iter()
+            .map(|path| Request {
+                url: format!("{}/{}", base, path),
+                ttl: 0,
+                refresh: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fetch_all_within_parallelism() {
+        let (base, peak, _) = serve(Duration::from_millis(100));
+        let http = cache("parallel", HttpConfig::default());
+        let paths = ["a", "b", "c", "d", "e", "f", "missing"];
+
+        let fetched = fetch_all(&http, requests(&base, &paths), 3);
+
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(fetched.len(), paths.len());
+        assert_eq!(fetched[&format!("{}/c", base)].body.as_deref(), Ok("/c"));
+        let missing = fetched[&format!("{}/missing", base)].body.as_ref();
+        assert!(missing.unwrap_err().contains("HTTP 404"));
+    }
+
+    #[test]
+    fn test_fetch_all_respects_rate_limit() {
+        let (base, _, arrivals) = serve(Duration::ZERO);
+        let config = HttpConfig {
+            rate_limit: Some(10.0),
+            ..HttpConfig::default()
+        };
+        let http = cache("rate", config);
+
+        fetch_all(&http, requests(&base, &["a", "b", "c", "d"]), 4);
+
+        let mut arrivals = arrivals.lock().unwrap().clone();
+        arrivals.sort();
+        assert_eq!(arrivals.len(), 4);
+        for pair in arrivals.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(80));
+        }
+    }
+
+    #[test]
+    fn test_discover() {
+        let file = std::env::temp_dir().join(format!("liaison-discover-{}.md", std::process::id()));
+        std::fs::write(
+            &file,
+            "<!-- liaison transclude=\"https://x.org/a.md#intro\" -->\n<!-- liaison end -->\n\
+             <!-- liaison transclude=\"local.md\" -->\n<!-- liaison end -->\n\
+             <div transclude=\"https://x.org/b.html?noindent\"></div>\n",
+        )
+        .unwrap();
+
+        let found: Vec<String> = discover(std::slice::from_ref(&file))
+            .into_iter()
+            .map(|r| r.uri)
+            .collect();
+        assert_eq!(found, ["https://x.org/a.md", "https://x.org/b.html"]);
+
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -2,8 +2,13 @@ use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use crate::resolver::{CycleDetector, Reference, Resolver};
-use crate::{html, plaintext};
+use crate::config::Config;
+use crate::lockfile::Lockfile;
+use crate::resolver::{
+    CycleDetector, IntegrityError, Reference, Resolver, is_html_uri, is_markdown_uri,
+    is_remote_uri, join_items,
+};
+use crate::{diff, html, links, literal, markdown, office, plaintext, policy, prefetch, sqlite};
 
 pub struct FileChange {
     pub path: PathBuf,
@@ -14,6 +19,9 @@ pub struct ProcessingResult {
     pub changes: Vec<FileChange>,
     pub dependencies: DependencyTree,
     pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    /// The lockfile passed in, updated with the remote sources fetched
+    pub lockfile: Lockfile,
 }
 
 #[derive(Debug, Default)]
@@ -84,11 +92,19 @@ pub fn reset_files(files: &[PathBuf]) -> Result<Vec<FileChange>> {
 /// Process all files and return the changes to be made
 pub fn process_files(
     repo_root: &Path,
+    config: &Config,
     files: &[PathBuf],
+    lockfile: Lockfile,
     ignore_errors: bool,
 ) -> Result<ProcessingResult> {
+    // Refuse disallowed URLs up front, before anything is fetched
+    policy::check_files(repo_root, files, &config.network)?;
+
     let mut changes = Vec::new();
-    let mut resolver = Resolver::new(repo_root.to_path_buf());
+    let mut resolver = Resolver::new(repo_root.to_path_buf(), config.clone());
+    resolver.use_lockfile(lockfile);
+    // Fetch the remote sources named in the files several at a time, before the walk needs them
+    resolver.prefetch(&prefetch::discover(files));
     let mut dependencies = DependencyTree::default();
     let mut errors = Vec::new();
 
@@ -125,7 +141,7 @@ pub fn process_files(
                 Ok(c) => c,
                 Err(e) => {
                     errors.push(format!("Error processing {:?}: {}", file, e));
-                    if ignore_errors {
+                    if tolerated(&e, ignore_errors) {
                         content.clone() // Keep original content on error
                     } else {
                         return Err(e);
@@ -144,7 +160,7 @@ pub fn process_files(
                 Ok(c) => c,
                 Err(e) => {
                     errors.push(format!("Error processing {:?}: {}", file, e));
-                    if ignore_errors {
+                    if tolerated(&e, ignore_errors) {
                         content.clone() // Keep original content on error
                     } else {
                         return Err(e);
@@ -165,9 +181,17 @@ pub fn process_files(
         changes,
         dependencies,
         errors,
+        warnings: resolver.take_warnings(),
+        lockfile: resolver.take_lockfile(),
     })
 }
 
+/// True if `--ignore-errors` lets processing continue past this error
+/// Integrity mismatches always fail the run.
+fn tolerated(error: &anyhow::Error, ignore_errors: bool) -> bool {
+    ignore_errors && error.downcast_ref::<IntegrityError>().is_none()
+}
+
 fn is_html_file(path: &Path) -> bool {
     if let Some(ext) = path.extension() {
         matches!(
@@ -206,6 +230,18 @@ fn reset_plaintext_file(content: &str, file: &Path) -> Result<String> {
     let parser = plaintext::PlaintextParser::new(file);
     let blocks = parser.parse(content)?;
 
+    // Clear inline markers first; they don't move any lines
+    let mut inline_cleared = content.to_string();
+    for block in blocks.iter().rev() {
+        if let plaintext::Block::Inline {
+            line, start, end, ..
+        } = block
+        {
+            inline_cleared = parser.replace_inline(&inline_cleared, *line, *start, *end, "");
+        }
+    }
+    let content = inline_cleared.as_str();
+
     let transclude_blocks: Vec<_> = blocks
         .into_iter()
         .filter_map(|b| match b {
@@ -263,10 +299,11 @@ fn process_html_file(
             &mut cycle_detector,
             dependencies,
             current_file,
+            Site::Element(block.element_id.as_deref()),
         ) {
             Ok(c) => c,
             Err(e) => {
-                if ignore_errors {
+                if tolerated(&e, ignore_errors) {
                     continue;
                 } else {
                     return Err(e);
@@ -277,15 +314,11 @@ fn process_html_file(
         if block.is_attribute_transclude() {
             result = html::replace_attribute(&result, &block, &resolved_content)?;
         } else {
-            let source_is_html_like = reference.uri.ends_with(".html")
-                || reference.uri.ends_with(".htm")
-                || reference.uri.ends_with(".md")
-                || reference.uri.ends_with(".markdown");
             result = html::replace_inner_html(
                 &result,
                 &block,
                 &resolved_content,
-                source_is_html_like,
+                renders_html(&reference),
                 reference.indent_override,
             )?;
         }
@@ -315,6 +348,18 @@ fn process_plaintext_file(
     let parser = plaintext::PlaintextParser::new(file);
     let blocks = parser.parse(content)?;
 
+    // Inline markers never change line counts, so block positions stay valid
+    let inline_expanded = expand_inline_transcludes(
+        content,
+        &blocks,
+        current_file,
+        resolver,
+        &mut CycleDetector::new(),
+        dependencies,
+        ignore_errors,
+    )?;
+    let content = inline_expanded.as_str();
+
     let transclude_blocks: Vec<_> = blocks
         .into_iter()
         .filter_map(|b| match b {
@@ -353,10 +398,11 @@ fn process_plaintext_file(
             &mut cycle_detector,
             dependencies,
             current_file,
+            Site::Comment,
         ) {
             Ok(c) => c,
             Err(e) => {
-                if ignore_errors {
+                if tolerated(&e, ignore_errors) {
                     continue; // Skip this block
                 } else {
                     return Err(e);
@@ -364,12 +410,40 @@ fn process_plaintext_file(
             }
         };
 
+        let resolved_content = indent_to_marker(content, start_line, &resolved_content, &reference);
         result = parser.replace_content(&result, start_line, end_line, &resolved_content);
     }
 
     Ok(result)
 }
 
+/// Where a transclusion lands in its host
+#[derive(Debug, Clone, Copy)]
+enum Site<'a> {
+    /// An HTML element with a transclude attribute, and that element's `id`
+    Element(Option<&'a str>),
+    /// A `liaison transclude` comment marker
+    Comment,
+}
+
+/// True if resolved content is already HTML and must not be escaped at element sites
+fn renders_html(reference: &Reference) -> bool {
+    reference.is_diff()
+        || is_html_uri(&reference.uri)
+        || is_markdown_uri(&reference.uri)
+        || (is_tabular(reference) && matches!(reference.option("format"), None | Some("html")))
+}
+
+/// True for office documents and databases, which render in a `?format=` chosen by the host
+fn is_tabular(reference: &Reference) -> bool {
+    office::is_office(&reference.uri) || sqlite::is_database(&reference.uri)
+}
+
+/// True if content landing at this site should be HTML rather than Markdown or text
+fn site_is_html(current_file: &str, site: Site) -> bool {
+    matches!(site, Site::Element(_)) || is_html_uri(current_file)
+}
+
 /// Recursively resolve a reference and expand its content
 fn resolve_recursive(
     reference: &Reference,
@@ -377,27 +451,299 @@ fn resolve_recursive(
     cycle_detector: &mut CycleDetector,
     dependencies: &mut DependencyTree,
     current_file: &str,
+    site: Site,
 ) -> Result<String> {
+    // References inside remote content are relative to its URL, never to the repository
+    let rebased;
+    let reference = if is_remote_uri(current_file) {
+        rebased = reference.relative_to_url(current_file)?;
+        &rebased
+    } else {
+        reference
+    };
+
     cycle_detector.enter(reference)?;
+    // Leave the reference on every path, so a tolerated error doesn't read as a cycle later
+    let result = resolve_entered(
+        reference,
+        resolver,
+        cycle_detector,
+        dependencies,
+        current_file,
+        site,
+    );
+    cycle_detector.exit(reference);
+    result
+}
 
-    // Track the dependency
-    dependencies.add_dependency(current_file.to_string(), reference.uri.clone());
+/// The body of `resolve_recursive`, run between entering and leaving the reference
+fn resolve_entered(
+    reference: &Reference,
+    resolver: &mut Resolver,
+    cycle_detector: &mut CycleDetector,
+    dependencies: &mut DependencyTree,
+    current_file: &str,
+    site: Site,
+) -> Result<String> {
+    // Track the dependency, with one edge per matched file for globs and wildcard ids
+    let multi = resolver.is_multi(reference, Some(current_file));
+    if multi {
+        let mut files: Vec<String> = resolver
+            .expand(reference, Some(current_file))?
+            .iter()
+            .map(|item| item.display_uri())
+            .collect();
+        files.dedup();
+        for file in files {
+            dependencies.add_dependency(current_file.to_string(), file);
+        }
+    } else {
+        dependencies.add_dependency(current_file.to_string(), reference.display_uri());
+    }
 
+    // Office documents and databases render as HTML or Markdown to suit the host
+    // unless `?format=` says otherwise
+    let tabular_reference;
+    let reference = if is_tabular(reference) && reference.option("format").is_none() {
+        let mut with_format = reference.clone();
+        let format = if site_is_html(current_file, site) {
+            "html"
+        } else {
+            "md"
+        };
+        with_format
+            .options
+            .insert("format".to_string(), format.to_string());
+        tabular_reference = with_format;
+        &tabular_reference
+    } else {
+        reference
+    };
+
+    // Diffs are rendered for the host as-is; their lines are not transcludes
+    if reference.is_diff() {
+        let (content, _) = resolver.resolve(reference, Some(current_file))?;
+        return Ok(render_diff(&content, current_file, site));
+    }
+
+    // Steps 1-3 depend on where the content came from, so each item of a glob or
+    // wildcard id goes through them against its own path before the items are joined
+    let (expanded, resolved_path) = if multi {
+        let mut resolved = Vec::new();
+        for item in resolver.expand(reference, Some(current_file))? {
+            let (content, resolved_path) = resolve_item(
+                &item,
+                resolver,
+                cycle_detector,
+                dependencies,
+                current_file,
+                site,
+            )?;
+            resolved.push((item, content, resolved_path));
+        }
+        // Items share a type, so the first one's path stands in for the set
+        let first_path = resolved
+            .first()
+            .map(|(_, _, path)| path.clone())
+            .unwrap_or_default();
+        (join_items(reference, &resolved), first_path)
+    } else {
+        resolve_item(
+            reference,
+            resolver,
+            cycle_detector,
+            dependencies,
+            current_file,
+            site,
+        )?
+    };
+
+    // Step 4: Shift heading levels to fit under the host's headings
+    let expanded = shift_headings(&expanded, reference, &resolved_path)?;
+
+    // Step 5: Scope ids so repeated fragments don't collide
+    let host_id = match site {
+        Site::Element(id) => id,
+        Site::Comment => None,
+    };
+    let expanded = scope_ids(&expanded, reference, &resolved_path, host_id)?;
+
+    // Step 6: Encode as a literal for the host language
+    let expanded = match reference.option("as") {
+        Some(form) => literal::encode(&expanded, form)?,
+        None => expanded,
+    };
+
+    Ok(expanded)
+}
+
+/// Follow one reference, expand the transcludes inside it and rebase its links,
+/// returning the content and the path it resolved to
+fn resolve_item(
+    reference: &Reference,
+    resolver: &mut Resolver,
+    cycle_detector: &mut CycleDetector,
+    dependencies: &mut DependencyTree,
+    current_file: &str,
+    site: Site,
+) -> Result<(String, String)> {
     // Step 1: Follow the reference to get content
     let (content, resolved_path) = resolver.resolve(reference, Some(current_file))?;
 
+    // Remote content may only transclude further if the network policy allows it
+    if is_remote_uri(&resolved_path)
+        && !resolver.config().network.nested
+        && !policy::references_in(&content, &resolved_path)?.is_empty()
+    {
+        return Err(anyhow::anyhow!(
+            "{} contains transclusions of its own, which network.nested = false forbids",
+            reference.display_uri()
+        ));
+    }
+
     // Step 2: Expand any transcludes within that content
-    let expanded = expand_content(
-        &content,
-        &resolved_path,
-        resolver,
-        cycle_detector,
-        dependencies,
-    )?;
+    // Command output, metadata and tabular sources are data, so markers inside them are left alone
+    let expanded = if reference.is_command() || reference.is_metadata() || is_tabular(reference) {
+        content
+    } else {
+        expand_content(
+            &content,
+            &resolved_path,
+            resolver,
+            cycle_detector,
+            dependencies,
+        )?
+    };
+
+    // Remote content that lands unescaped in an HTML or Markdown host is rendered
+    // as markup whatever its own type, so it is sanitized here too
+    let lands_unescaped = matches!(site, Site::Comment) || renders_html(reference);
+    let markup_host = is_html_uri(current_file) || is_markdown_uri(current_file);
+    let expanded = if is_remote_uri(&resolved_path)
+        && markup_host
+        && lands_unescaped
+        && resolver.config().sanitize.remote
+    {
+        resolver.sanitize(&expanded, &reference.uri)?
+    } else {
+        expanded
+    };
 
-    cycle_detector.exit(reference);
+    // Step 3: Rebase relative links so they resolve from the host's location
+    let expanded = if reference.has_option("norewrite") {
+        expanded
+    } else {
+        links::rewrite_for_host(&expanded, &resolved_path, current_file)?
+    };
 
-    Ok(expanded)
+    Ok((expanded, resolved_path))
+}
+
+/// Apply `?shift-headings=N` or `?heading-base=N` to Markdown and HTML sources
+fn shift_headings(content: &str, reference: &Reference, source_path: &str) -> Result<String> {
+    let is_markdown = is_markdown_uri(source_path);
+    let is_html = is_html_uri(source_path);
+    if !is_markdown && !is_html {
+        return Ok(content.to_string());
+    }
+
+    let parse_level = |name: &str, value: &str| {
+        value
+            .parse::<i32>()
+            .with_context(|| format!("Invalid {} value '{}': expected an integer", name, value))
+    };
+
+    let delta = if let Some(value) = reference.option("shift-headings") {
+        parse_level("shift-headings", value)?
+    } else if let Some(value) = reference.option("heading-base") {
+        let base = parse_level("heading-base", value)?;
+        let min_level = if is_markdown {
+            markdown::min_heading_level(content)
+        } else {
+            html::min_heading_level(content)?
+        };
+        match min_level {
+            Some(level) => base.saturating_sub(level as i32),
+            None => 0,
+        }
+    } else {
+        return Ok(content.to_string());
+    };
+
+    if is_markdown {
+        Ok(markdown::shift_headings(content, delta))
+    } else {
+        html::shift_headings(content, delta)
+    }
+}
+
+/// Render a unified diff for its site: HTML spans in HTML, a fence in Markdown
+fn render_diff(unified: &str, current_file: &str, site: Site) -> String {
+    let markdown_host = is_markdown_uri(current_file);
+    if site_is_html(current_file, site) {
+        diff::to_html(unified)
+    } else if markdown_host {
+        diff::to_markdown(unified)
+    } else {
+        unified.to_string()
+    }
+}
+
+/// Apply `?id-prefix` / `?id-suffix` to HTML sources
+/// A bare option derives the affix from the transcluding element's own id
+fn scope_ids(
+    content: &str,
+    reference: &Reference,
+    source_path: &str,
+    host_id: Option<&str>,
+) -> Result<String> {
+    if !is_html_uri(source_path) {
+        return Ok(content.to_string());
+    }
+
+    let affix = |name: &str| -> Result<Option<String>> {
+        match reference.option(name) {
+            None => Ok(None),
+            Some("") => host_id.map(|id| Some(id.to_string())).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "'?{}' without a value needs the transcluding element to have an id: {}",
+                    name,
+                    reference.uri
+                )
+            }),
+            Some(value) => Ok(Some(value.to_string())),
+        }
+    };
+
+    match (affix("id-prefix")?, affix("id-suffix")?) {
+        (None, None) => Ok(content.to_string()),
+        (prefix, suffix) => {
+            // Derived affixes get a separator; explicit ones are used verbatim
+            let derived = |name: &str| reference.option(name) == Some("");
+            let prefix = prefix.map(|p| {
+                if derived("id-prefix") {
+                    format!("{}-", p)
+                } else {
+                    p
+                }
+            });
+            let suffix = suffix.map(|s| {
+                if derived("id-suffix") {
+                    format!("-{}", s)
+                } else {
+                    s
+                }
+            });
+            html::rename_ids(content, |id| {
+                format!(
+                    "{}{}{}",
+                    prefix.as_deref().unwrap_or(""),
+                    id,
+                    suffix.as_deref().unwrap_or("")
+                )
+            })
+        }
+    }
 }
 
 /// Expand all transcludes within content, based on the host file type
@@ -408,7 +754,7 @@ fn expand_content(
     cycle_detector: &mut CycleDetector,
     dependencies: &mut DependencyTree,
 ) -> Result<String> {
-    let is_html_host = source_path.ends_with(".html") || source_path.ends_with(".htm");
+    let is_html_host = is_html_uri(source_path);
 
     if is_html_host {
         // HTML hosts: element transcludes + comment transcludes (both with indentation)
@@ -444,23 +790,19 @@ fn expand_html_transcludes(
 
     for block in blocks {
         let reference = Reference::parse(&block.reference)?;
-        // Don't escape if source is HTML or Markdown (which can contain HTML)
-        let source_is_html_like = reference.uri.ends_with(".html")
-            || reference.uri.ends_with(".htm")
-            || reference.uri.ends_with(".md")
-            || reference.uri.ends_with(".markdown");
         let resolved = resolve_recursive(
             &reference,
             resolver,
             cycle_detector,
             dependencies,
             current_file,
+            Site::Element(block.element_id.as_deref()),
         )?;
         result = html::replace_inner_html(
             &result,
             &block,
             &resolved,
-            source_is_html_like,
+            renders_html(&reference),
             reference.indent_override,
         )?;
     }
@@ -480,6 +822,18 @@ fn expand_html_comment_transcludes(
     let parser = plaintext::PlaintextParser::new(path);
     let blocks = parser.parse(content)?;
 
+    // Inline markers never change line counts, so block positions stay valid
+    let inline_expanded = expand_inline_transcludes(
+        content,
+        &blocks,
+        uri,
+        resolver,
+        cycle_detector,
+        dependencies,
+        false,
+    )?;
+    let content = inline_expanded.as_str();
+
     let transclude_blocks: Vec<_> = blocks
         .into_iter()
         .filter_map(|b| match b {
@@ -501,7 +855,14 @@ fn expand_html_comment_transcludes(
 
     for (reference, start_line, end_line) in transclude_blocks.into_iter().rev() {
         let reference = Reference::parse(&reference)?;
-        let resolved = resolve_recursive(&reference, resolver, cycle_detector, dependencies, uri)?;
+        let resolved = resolve_recursive(
+            &reference,
+            resolver,
+            cycle_detector,
+            dependencies,
+            uri,
+            Site::Comment,
+        )?;
 
         // HTML hosts apply the marker's indentation to the resolved content
         let marker_line = lines.get(start_line).unwrap_or(&"");
@@ -529,6 +890,18 @@ fn expand_plaintext_transcludes(
     let parser = plaintext::PlaintextParser::new(path);
     let blocks = parser.parse(content)?;
 
+    // Inline markers never change line counts, so block positions stay valid
+    let inline_expanded = expand_inline_transcludes(
+        content,
+        &blocks,
+        uri,
+        resolver,
+        cycle_detector,
+        dependencies,
+        false,
+    )?;
+    let content = inline_expanded.as_str();
+
     let transclude_blocks: Vec<_> = blocks
         .into_iter()
         .filter_map(|b| match b {
@@ -549,13 +922,93 @@ fn expand_plaintext_transcludes(
 
     for (reference, start_line, end_line) in transclude_blocks.into_iter().rev() {
         let reference = Reference::parse(&reference)?;
-        let resolved = resolve_recursive(&reference, resolver, cycle_detector, dependencies, uri)?;
+        let resolved = resolve_recursive(
+            &reference,
+            resolver,
+            cycle_detector,
+            dependencies,
+            uri,
+            Site::Comment,
+        )?;
+        let resolved = indent_to_marker(content, start_line, &resolved, &reference);
         result = parser.replace_content(&result, start_line, end_line, &resolved);
     }
 
     Ok(result)
 }
 
+/// Expand single-line transcludes that sit inside a line of text
+fn expand_inline_transcludes(
+    content: &str,
+    blocks: &[plaintext::Block],
+    current_file: &str,
+    resolver: &mut Resolver,
+    cycle_detector: &mut CycleDetector,
+    dependencies: &mut DependencyTree,
+    ignore_errors: bool,
+) -> Result<String> {
+    let parser = plaintext::PlaintextParser::new(Path::new(current_file));
+    let mut result = content.to_string();
+
+    // Reverse order keeps earlier byte offsets on the same line valid
+    for block in blocks.iter().rev() {
+        let plaintext::Block::Inline {
+            reference,
+            line,
+            start,
+            end,
+        } = block
+        else {
+            continue;
+        };
+
+        let resolved = Reference::parse(reference).and_then(|reference| {
+            let resolved = resolve_recursive(
+                &reference,
+                resolver,
+                cycle_detector,
+                dependencies,
+                current_file,
+                Site::Comment,
+            )?;
+            let value = resolved.trim_end_matches('\n');
+            if value.contains('\n') {
+                return Err(anyhow::anyhow!(
+                    "Inline transclude must resolve to a single line: {}",
+                    reference.uri
+                ));
+            }
+            Ok(value.to_string())
+        });
+
+        match resolved {
+            Ok(value) => result = parser.replace_inline(&result, *line, *start, *end, &value),
+            Err(e) if tolerated(&e, ignore_errors) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Plaintext hosts don't indent by default; `?indent` applies the marker's indentation
+fn indent_to_marker(
+    content: &str,
+    start_line: usize,
+    resolved: &str,
+    reference: &Reference,
+) -> String {
+    if reference.indent_override != Some(true) {
+        return resolved.to_string();
+    }
+    let marker_line = content.lines().nth(start_line).unwrap_or("");
+    let indent: String = marker_line
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+    html::indent_lines(resolved, &indent)
+}
+
 /// Apply changes to files atomically (all or nothing)
 pub fn apply_changes(changes: &[FileChange]) -> Result<()> {
     // First, verify all writes will succeed by doing a dry run
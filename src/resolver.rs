@@ -1,9 +1,25 @@
 use anyhow::{Context, Result, anyhow};
 use base64::Engine;
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
 use std::process::Command;
 
+use crate::archive;
+use crate::command;
+use crate::config::Config;
+use crate::crates;
+use crate::diff;
+use crate::http::{self, HttpCache};
+use crate::lockfile::Lockfile;
+use crate::metadata;
+use crate::office;
+use crate::policy;
+use crate::prefetch::{self, Fetched};
+use crate::sandbox::{self, Sandbox};
+use crate::sqlite;
+use crate::table::{self, Format};
+
 /// Find the git repository root for a given path
 pub fn find_repo_root_for_path(path: &PathBuf) -> Result<PathBuf> {
     // Get the directory containing the file (or the directory itself if it's a directory)
@@ -45,64 +61,557 @@ pub struct Reference {
     pub transform: Option<String>,
     /// Override indentation behavior: Some(true) = force indent, Some(false) = force no indent
     pub indent_override: Option<bool>,
+    /// Git revision to read the file at (`path@rev` or `?rev=`), instead of the working tree
+    pub rev: Option<String>,
+    /// Named options such as `norewrite`; `key=value` params keep their value,
+    /// bare flags map to an empty string
+    pub options: BTreeMap<String, String>,
+}
+
+/// Scheme for unified diffs between two revisions or two files
+const DIFF_SCHEME: &str = "diff:";
+
+/// Scheme for the output of an allowlisted command
+const CMD_SCHEME: &str = "cmd:";
+
+/// Scheme for files of crates available locally
+const CRATE_SCHEME: &str = "crate:";
+
+pub fn is_remote_uri(uri: &str) -> bool {
+    uri.starts_with("http://") || uri.starts_with("https://")
+}
+
+/// The part of a URI that names the file, and so decides how it's read
+/// For URLs that's the URL path, without the query string or fragment.
+pub fn uri_path(uri: &str) -> Cow<'_, str> {
+    if is_remote_uri(uri)
+        && let Ok(url) = reqwest::Url::parse(uri)
+    {
+        return Cow::Owned(url.path().to_string());
+    }
+    Cow::Borrowed(uri)
+}
+
+/// True if the URI names an HTML file
+pub fn is_html_uri(uri: &str) -> bool {
+    let path = uri_path(uri);
+    path.ends_with(".html") || path.ends_with(".htm")
+}
+
+/// True if the URI names a Markdown file
+pub fn is_markdown_uri(uri: &str) -> bool {
+    let path = uri_path(uri);
+    path.ends_with(".md") || path.ends_with(".markdown")
+}
+
+/// Media types whose content is rendered as markup, and so is sanitized
+const MARKUP_TYPES: &[&str] = &["text/html", "application/xhtml+xml", "text/markdown"];
+
+/// True for paths into the repository, as opposed to URLs and other schemes
+fn is_local_path(uri: &str) -> bool {
+    !is_remote_uri(uri)
+        && !uri.starts_with(DIFF_SCHEME)
+        && !uri.starts_with(CMD_SCHEME)
+        && !uri.starts_with(CRATE_SCHEME)
+        && !metadata::is_metadata(uri)
+}
+
+/// The host file's directory, for file-relative lookups
+fn host_dir(current_file_path: Option<&str>) -> Option<PathBuf> {
+    let dir = std::path::Path::new(current_file_path?).parent()?;
+    (!dir.is_absolute()).then(|| dir.to_path_buf())
+}
+
+/// Match an id against a pattern where `*` stands for any run of characters
+fn wildcard_match(pattern: &str, id: &str) -> bool {
+    let regex = format!(
+        "^{}$",
+        pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*")
+    );
+    regex::Regex::new(&regex).is_ok_and(|re| re.is_match(id))
+}
+
+/// Join the resolved items of a multi reference, given as (item, content, resolved path)
+/// `?separator=` goes between items (default: a blank line); `?header` puts
+/// a comment naming the item before each one, and `?header=TEMPLATE` a line
+/// built from `{path}`, `{name}` and `{id}`. Trailing newlines are trimmed from
+/// each item so the separator alone decides the spacing.
+pub fn join_items(reference: &Reference, items: &[(Reference, String, String)]) -> String {
+    let separator = reference
+        .option("separator")
+        .map(unescape_separator)
+        .unwrap_or_else(|| "\n\n".to_string());
+
+    let parts: Vec<String> = items
+        .iter()
+        .map(|(item, content, resolved_path)| {
+            let content = content.trim_end_matches(['\n', '\r']);
+            let header = reference.option("header").map(|template| {
+                let path = std::path::Path::new(resolved_path);
+                let id = item.selector.as_deref().unwrap_or("");
+                if template.is_empty() {
+                    let label = match &item.selector {
+                        Some(id) => format!("{}#{}", resolved_path, id),
+                        None => resolved_path.clone(),
+                    };
+                    crate::plaintext::PlaintextParser::new(path).comment(&label)
+                } else {
+                    let name = path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    template
+                        .replace("{path}", resolved_path)
+                        .replace("{name}", &name)
+                        .replace("{id}", id)
+                }
+            });
+            match header {
+                Some(header) => format!("{}\n{}", header, content),
+                None => content.to_string(),
+            }
+        })
+        .collect();
+    parts.join(&separator)
+}
+
+/// Undo the `\n` and `\t` escapes allowed in `?separator=`
+fn unescape_separator(value: &str) -> String {
+    value.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+/// Bare parameters that are options rather than transforms
+const OPTION_FLAGS: &[&str] = &[
+    "norewrite",
+    "id-prefix",
+    "id-suffix",
+    "linenos",
+    "permalink",
+    "header",
+];
+
+/// Characters that can be written as `%XX` in a reference, so they lose their
+/// meaning as delimiters; any other `%` is taken literally
+const ESCAPABLE: &[char] = &['#', '?', '@', '&', '=', '%', '.'];
+
+/// Percent-escape the delimiters in one part of a reference
+/// A `%` is escaped only where it would otherwise read as an escape.
+fn escape_part(text: &str, delimiters: &[char]) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (i, c) in text.char_indices() {
+        let reads_as_escape = c == '%' && unescape_at(&text[i..]).is_some();
+        if reads_as_escape || delimiters.contains(&c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// The character escaped at the start of `text`, if it begins with one
+fn unescape_at(text: &str) -> Option<char> {
+    let hex = text.strip_prefix('%')?.get(..2)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let c = char::from(u8::from_str_radix(hex, 16).ok()?);
+    ESCAPABLE.contains(&c).then_some(c)
+}
+
+/// Undo `escape_part`
+fn unescape_part(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('%') {
+        unescaped.push_str(&rest[..i]);
+        match unescape_at(&rest[i..]) {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[i + 3..];
+            }
+            None => {
+                unescaped.push('%');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Split reference text into its target, selector and parameters
+///
+/// The target runs to the first `#` or `?`; from a `#`, the selector runs to
+/// the next `?`, and the parameters are the rest. A URL's target runs to the
+/// first `#`, so it keeps its own query string when a selector follows
+/// (`https://host/doc?v=2#intro?noindent`) or an empty selector does
+/// (`https://host/doc?v=2#`). `diff:` and `cmd:` targets run to the first `?`
+/// and have no selector.
+pub fn split_reference(s: &str) -> (&str, Option<&str>, Option<&str>) {
+    let target_end = if s.starts_with(DIFF_SCHEME) || s.starts_with(CMD_SCHEME) {
+        s.find('?')
+    } else if is_remote_uri(s) {
+        s.find('#').or_else(|| s.find('?'))
+    } else {
+        s.find(['#', '?'])
+    };
+    let Some(end) = target_end else {
+        return (s, None, None);
+    };
+
+    let target = &s[..end];
+    match s[end..].strip_prefix('#') {
+        Some(rest) => match rest.split_once('?') {
+            Some((selector, params)) => (target, Some(selector), Some(params)),
+            None => (target, Some(rest), None),
+        },
+        None => (target, None, Some(&s[end + 1..])),
+    }
+}
+
+/// Delimiters that must be escaped in a target of this kind
+/// URLs keep their own percent-encoding, and a diff spec holds references
+/// that are parsed again, so neither is escaped at all.
+fn target_delimiters(uri: &str) -> &'static [char] {
+    if is_remote_uri(uri) || uri.starts_with(DIFF_SCHEME) {
+        &[]
+    } else if uri.starts_with(CMD_SCHEME) {
+        &['?']
+    } else if is_local_path(uri) {
+        &['#', '?', '@']
+    } else {
+        &['#', '?']
+    }
 }
 
 impl Reference {
+    /// Parse reference text
+    ///
+    /// Grammar: `TARGET[@REV][#SELECTOR][?PARAM[&PARAM]...]`, split as described
+    /// in `split_reference`. A parameter is `indent`, `noindent`, an option flag,
+    /// `key=value` (the value keeps any later `=`) or otherwise the transform.
+    /// Outside URLs, `%23` `%3F` `%40` `%26` `%3D` `%25` and `%2E` stand for
+    /// `#` `?` `@` `&` `=` `%` and `.`; `%2E` keeps a `.` from reading as part
+    /// of a `..` diff separator.
     pub fn parse(s: &str) -> Result<Self> {
-        // Split on '?' first to extract transform/query parameters
-        let (base, params_str) = if let Some((b, t)) = s.split_once('?') {
-            (b, Some(t))
-        } else {
-            (s, None)
-        };
+        let (target, selector, params) = split_reference(s);
 
-        // Parse parameters (supports & separator for multiple params)
         let mut transform = None;
         let mut indent_override = None;
+        let mut options = BTreeMap::new();
 
-        if let Some(params) = params_str {
+        if let Some(params) = params {
             for param in params.split('&') {
                 match param {
                     "indent" => indent_override = Some(true),
                     "noindent" => indent_override = Some(false),
-                    other if !other.is_empty() => transform = Some(other.to_string()),
+                    flag if OPTION_FLAGS.contains(&flag) => {
+                        options.insert(flag.to_string(), String::new());
+                    }
+                    other if other.contains('=') => {
+                        let (key, value) = other.split_once('=').unwrap();
+                        options.insert(unescape_part(key), unescape_part(value));
+                    }
+                    other if !other.is_empty() => transform = Some(unescape_part(other)),
                     _ => {}
                 }
             }
         }
 
-        // Then split on '#' to extract selector
-        if let Some((uri, selector)) = base.split_once('#') {
-            Ok(Reference {
-                uri: uri.to_string(),
-                selector: Some(selector.to_string()),
-                transform,
-                indent_override,
-            })
-        } else {
-            Ok(Reference {
-                uri: base.to_string(),
-                selector: None,
-                transform,
-                indent_override,
-            })
+        let selector = selector
+            .filter(|selector| !selector.is_empty())
+            .map(unescape_part);
+
+        // A local path may pin a revision with `path@rev`
+        let (uri, rev) = match target.rsplit_once('@') {
+            Some((path, rev)) if is_local_path(target) && !path.is_empty() && !rev.is_empty() => {
+                (unescape_part(path), Some(unescape_part(rev)))
+            }
+            _ if target_delimiters(target).is_empty() => {
+                (target.to_string(), options.remove("rev"))
+            }
+            _ => (unescape_part(target), options.remove("rev")),
+        };
+
+        Ok(Reference {
+            uri,
+            selector,
+            transform,
+            indent_override,
+            rev,
+            options,
+        })
+    }
+
+    /// True for `diff:` references, which resolve to a unified diff
+    pub fn is_diff(&self) -> bool {
+        self.uri.starts_with(DIFF_SCHEME)
+    }
+
+    /// True for `cmd:` references, which resolve to a command's output
+    pub fn is_command(&self) -> bool {
+        self.uri.starts_with(CMD_SCHEME)
+    }
+
+    /// True for project metadata such as `cargo:package.version`
+    pub fn is_metadata(&self) -> bool {
+        metadata::is_metadata(&self.uri)
+    }
+
+    /// True if the local path has glob characters (`*`, `[`)
+    pub fn has_glob_path(&self) -> bool {
+        is_local_path(&self.uri) && self.uri.contains(['*', '['])
+    }
+
+    /// True if the selector is a `*` wildcard over plaintext ids
+    pub fn has_wildcard_id(&self) -> bool {
+        is_local_path(&self.uri)
+            && self.selector.as_deref().is_some_and(|s| s.contains('*'))
+            && !is_html_uri(&self.uri)
+    }
+
+    /// This reference as written inside remote content fetched from `base`
+    /// Paths are resolved against the base URL (RFC 3986), so a remote document
+    /// can only ever reach other http(s) URLs, never the local machine.
+    pub fn relative_to_url(&self, base: &str) -> Result<Reference> {
+        if is_remote_uri(&self.uri) {
+            return Ok(self.clone());
+        }
+        if !is_local_path(&self.uri) {
+            return Err(anyhow!(
+                "Remote content from {} can't reference {}",
+                http::redact_url(base),
+                self.display_uri()
+            ));
+        }
+
+        // Remote sources have no revisions, so `name@2x.png` is just a file name
+        let path = match &self.rev {
+            Some(rev) => format!("{}@{}", self.uri, rev),
+            None => self.uri.clone(),
+        };
+        let joined = reqwest::Url::parse(base)
+            .and_then(|base| base.join(&path))
+            .with_context(|| {
+                format!("Can't resolve {} against {}", path, http::redact_url(base))
+            })?;
+        if !is_remote_uri(joined.as_str()) {
+            return Err(anyhow!(
+                "Remote content from {} can't reference {}",
+                http::redact_url(base),
+                http::redact_url(joined.as_str())
+            ));
+        }
+
+        Ok(Reference {
+            uri: joined.to_string(),
+            rev: None,
+            ..self.clone()
+        })
+    }
+
+    /// The URI as written, including any `@rev` pin (for display and dependency tracking)
+    /// A password in a URL is redacted.
+    pub fn display_uri(&self) -> String {
+        let uri = http::redact_url(&self.uri);
+        match &self.rev {
+            Some(rev) => format!("{}@{}", uri, rev),
+            None => uri,
+        }
+    }
+
+    /// Returns true if the named option is present (as a flag or with a value)
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    /// Returns the value of a `key=value` option
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|v| v.as_str())
+    }
+}
+
+/// The canonical text of a reference, which `Reference::parse` reads back unchanged
+/// Parameters are written as indentation, transform, then options by name.
+impl std::fmt::Display for Reference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match target_delimiters(&self.uri) {
+            [] => write!(f, "{}", self.uri)?,
+            delimiters => write!(f, "{}", escape_part(&self.uri, delimiters))?,
+        }
+
+        // `@rev` only reads as a revision after a local path
+        let mut rev_option = None;
+        match &self.rev {
+            Some(rev) if is_local_path(&self.uri) && !self.uri.is_empty() && !rev.is_empty() => {
+                write!(f, "@{}", escape_part(rev, &['#', '?', '@']))?;
+            }
+            Some(rev) => rev_option = Some(rev),
+            None => {}
+        }
+
+        let mut params = Vec::new();
+        match self.indent_override {
+            Some(true) => params.push("indent".to_string()),
+            Some(false) => params.push("noindent".to_string()),
+            None => {}
         }
+        if let Some(transform) = &self.transform {
+            params.push(escape_part(transform, &['&', '=']));
+        }
+        let mut options = self.options.clone();
+        if let Some(rev) = rev_option {
+            options.insert("rev".to_string(), rev.clone());
+        }
+        for (key, value) in &options {
+            if value.is_empty() && OPTION_FLAGS.contains(&key.as_str()) {
+                params.push(key.clone());
+            } else {
+                params.push(format!(
+                    "{}={}",
+                    escape_part(key, &['&', '=']),
+                    escape_part(value, &['&'])
+                ));
+            }
+        }
+
+        match &self.selector {
+            Some(selector) => write!(f, "#{}", escape_part(selector, &['?']))?,
+            // A URL without a selector needs an empty one to keep its query
+            // string, or to keep a `#` in the parameters from starting one
+            None if is_remote_uri(&self.uri)
+                && (self.uri.contains('?') || params.iter().any(|p| p.contains('#'))) =>
+            {
+                write!(f, "#")?
+            }
+            None => {}
+        }
+        if !params.is_empty() {
+            write!(f, "?{}", params.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Content that doesn't match its `?sha256=` pin
+/// Unlike other resolution errors, `--ignore-errors` never skips it.
+#[derive(Debug)]
+pub struct IntegrityError {
+    pub reference: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Integrity mismatch for {}: expected sha256 {}, got {}",
+            self.reference, self.expected, self.actual
+        )
     }
 }
 
+impl std::error::Error for IntegrityError {}
+
 pub struct Resolver {
     repo_root: PathBuf,
-    cache: HashMap<Reference, String>,
+    config: Config,
+    /// Non-fatal issues (e.g. sanitized content) to report in the run summary
+    warnings: Vec<String>,
+    /// HEAD commit, looked up once for permalinks
+    head_commit: Option<String>,
+    /// Packages from `cargo metadata`, looked up once for `crate:` sources
+    crate_packages: Option<Vec<crates::Package>>,
+    /// Remote sources, cached on disk between runs
+    http: HttpCache,
+    /// Content hashes that remote sources are checked against
+    lockfile: Lockfile,
+    /// Confines local paths to the repository
+    sandbox: Sandbox,
+    /// Remote sources fetched ahead of the walk, by URL
+    prefetched: HashMap<String, Fetched>,
+    /// Keyed by reference and the directory it was resolved from, since
+    /// file-relative paths can point at different files from different hosts
+    cache: HashMap<(Reference, Option<String>), (String, String)>,
 }
 
 impl Resolver {
-    pub fn new(repo_root: PathBuf) -> Self {
+    pub fn new(repo_root: PathBuf, config: Config) -> Self {
+        let http = HttpCache::new(
+            repo_root.join(&config.cache.dir),
+            config.cache.mode,
+            config.http.clone(),
+            config.network.clone(),
+        );
+        let sandbox = Sandbox::new(&repo_root, config.paths.symlinks);
         Self {
             repo_root,
+            config,
+            http,
+            sandbox,
+            lockfile: Lockfile::default(),
+            prefetched: HashMap::new(),
+            warnings: Vec::new(),
+            head_commit: None,
+            crate_packages: None,
             cache: HashMap::new(),
         }
     }
 
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Check remote sources against this lockfile, recording new ones
+    pub fn use_lockfile(&mut self, lockfile: Lockfile) {
+        self.lockfile = lockfile;
+    }
+
+    /// Take the lockfile back, with the entries recorded during the run
+    pub fn take_lockfile(&mut self) -> Lockfile {
+        std::mem::take(&mut self.lockfile)
+    }
+
+    /// Fetch remote references ahead of resolution, several at a time
+    /// Failures are kept and reported when the walk reaches the reference, and
+    /// URLs the network policy forbids are left for the walk to refuse.
+    pub fn prefetch(&mut self, references: &[Reference]) {
+        let mut requests = Vec::new();
+        let mut seen = HashSet::new();
+        for reference in references {
+            let url = &reference.uri;
+            if !is_remote_uri(url)
+                || self.prefetched.contains_key(url)
+                || !seen.insert(url.clone())
+                || policy::check_url(url, &self.config.network).is_err()
+            {
+                continue;
+            }
+            let Ok(ttl) = http::ttl_for(url, reference.option("ttl"), &self.config.cache) else {
+                continue;
+            };
+            requests.push(prefetch::Request {
+                url: url.clone(),
+                ttl,
+                refresh: self.lockfile.wants_update(url),
+            });
+        }
+
+        let fetched = prefetch::fetch_all(&self.http, requests, self.config.http.parallelism);
+        self.prefetched.extend(fetched);
+    }
+
+    /// Take the warnings collected so far
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
     /// Resolve a reference to its content
     /// current_file_path is the path to the file containing the reference (for relative resolution)
     /// Returns (content, resolved_path) where resolved_path is the actual file path that was loaded
@@ -111,21 +620,138 @@ impl Resolver {
         reference: &Reference,
         current_file_path: Option<&str>,
     ) -> Result<(String, String)> {
-        if let Some(cached) = self.cache.get(reference) {
-            // For cached content, the resolved path is just the URI
-            // (we don't cache the resolved path, but that's okay for now)
-            return Ok((cached.clone(), reference.uri.clone()));
-        }
-
-        let (content, resolved_path) =
-            if reference.uri.starts_with("http://") || reference.uri.starts_with("https://") {
-                (self.fetch_http(&reference.uri)?, reference.uri.clone())
-            } else if reference.transform.as_deref() == Some("dataurl") {
-                // For dataurl transform, read as binary
-                self.fetch_local_binary(&reference.uri, current_file_path)?
-            } else {
-                self.fetch_local(&reference.uri, current_file_path)?
+        let (content, resolved_path) = self.resolve_unverified(reference, current_file_path)?;
+
+        // `?sha256=` pins the selected content, after transforms and decoration
+        if let Some(expected) = reference.option("sha256") {
+            let actual = http::sha256_hex(content.as_bytes());
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err(IntegrityError {
+                    reference: reference.display_uri(),
+                    expected: expected.to_string(),
+                    actual,
+                }
+                .into());
+            }
+        }
+
+        Ok((content, resolved_path))
+    }
+
+    fn resolve_unverified(
+        &mut self,
+        reference: &Reference,
+        current_file_path: Option<&str>,
+    ) -> Result<(String, String)> {
+        // Callers resolve references in remote content against its URL first;
+        // anything else would read local files on behalf of a remote document
+        if let Some(base) = current_file_path.filter(|p| is_remote_uri(p))
+            && !is_remote_uri(&reference.uri)
+        {
+            return Err(anyhow!(
+                "Remote content from {} can't reference local source {}",
+                http::redact_url(base),
+                reference.display_uri()
+            ));
+        }
+
+        // Commands and metadata don't depend on the host, so one result serves every host
+        let base_dir = current_file_path
+            .filter(|_| !reference.is_command() && !reference.is_metadata())
+            .and_then(|p| std::path::Path::new(p).parent())
+            .map(|d| d.to_string_lossy().to_string());
+        let cache_key = (reference.clone(), base_dir);
+        if let Some(cached) = self.cache.get(&cache_key) {
+            return Ok(cached.clone());
+        }
+
+        if self.is_multi(reference, current_file_path) {
+            let result = self.resolve_multi(reference, current_file_path)?;
+            self.cache.insert(cache_key, result.clone());
+            return Ok(result);
+        }
+
+        if reference.is_metadata() {
+            let manifest = match reference.option("manifest") {
+                Some(manifest) => {
+                    let manifest = sandbox::normalize(std::path::Path::new(""), manifest)?;
+                    self.sandbox.confine(&manifest)?;
+                    Some(manifest.to_string_lossy().to_string())
+                }
+                None => None,
             };
+            let value = metadata::resolve(
+                &reference.uri,
+                manifest.as_deref(),
+                &self.repo_root,
+                &self.config,
+            )?;
+            let result = (value, reference.uri.clone());
+            self.cache.insert(cache_key, result.clone());
+            return Ok(result);
+        }
+
+        if let Some(command) = reference.uri.strip_prefix(CMD_SCHEME) {
+            let output = command::run(command, &self.config.cmd, &self.repo_root)?;
+            let result = (output, reference.uri.clone());
+            self.cache.insert(cache_key, result.clone());
+            return Ok(result);
+        }
+
+        if let Some(spec) = reference.uri.strip_prefix(DIFF_SCHEME) {
+            let result = self.resolve_diff(spec, current_file_path)?;
+            self.cache.insert(cache_key, result.clone());
+            return Ok(result);
+        }
+
+        let is_remote = is_remote_uri(&reference.uri);
+        let binary = reference.transform.as_deref() == Some("dataurl");
+        if sqlite::is_database(&reference.uri) && !binary {
+            let result = self.resolve_database(reference, current_file_path)?;
+            self.cache.insert(cache_key, result.clone());
+            return Ok(result);
+        }
+        if office::is_office(&reference.uri) && !binary {
+            let result = self.resolve_office(reference, current_file_path)?;
+            self.cache.insert(cache_key, result.clone());
+            return Ok(result);
+        }
+        let revision = reference
+            .rev
+            .as_deref()
+            .filter(|_| !self.is_literal_at_path(reference, current_file_path));
+        let archive_member = reference
+            .uri
+            .split_once(archive::MEMBER_SEPARATOR)
+            .filter(|_| !is_remote);
+        let (content, resolved_path) = if is_remote {
+            (self.fetch_http(reference)?, reference.uri.clone())
+        } else if let Some(spec) = reference.uri.strip_prefix(CRATE_SCHEME) {
+            (self.fetch_crate(spec, binary)?, reference.uri.clone())
+        } else if let Some((archive_path, member)) = archive_member {
+            if reference.rev.is_some() {
+                return Err(anyhow!(
+                    "Archive members can't be read at a revision: {}",
+                    reference.display_uri()
+                ));
+            }
+            self.fetch_archive_member(archive_path, member, current_file_path, binary)?
+        } else if let Some(rev) = revision {
+            self.fetch_git(&reference.uri, rev, current_file_path, binary)?
+        } else if let Some(rev) = &reference.rev {
+            // `name@2x.png` is a file name, not a revision pin
+            let literal = format!("{}@{}", reference.uri, rev);
+            if binary {
+                self.fetch_local_binary(&literal, current_file_path)?
+            } else {
+                self.fetch_local(&literal, current_file_path)?
+            }
+        } else if binary {
+            // For dataurl transform, read as binary
+            self.fetch_local_binary(&reference.uri, current_file_path)?
+        } else {
+            self.fetch_local(&reference.uri, current_file_path)?
+        };
 
         // Apply transform if specified
         let transformed = if let Some(transform) = &reference.transform {
@@ -134,122 +760,467 @@ impl Resolver {
             content
         };
 
-        let result = if let Some(selector) = &reference.selector {
+        let (result, lines) = if let Some(selector) = &reference.selector {
             self.extract_content(&transformed, &reference.uri, selector)?
         } else {
             self.extract_default(&transformed, &reference.uri)?
         };
 
-        self.cache.insert(reference.clone(), result.clone());
+        let result = self.decorate_lines(result, lines, reference, &resolved_path, revision)?;
+
+        // Sanitize HTML per policy (strict by default for remote sources)
+        // Markdown can carry inline HTML too, and a remote source is also judged
+        // by the Content-Type its server sent, since its URL may have no extension.
+        let is_markup = is_html_uri(&reference.uri)
+            || is_markdown_uri(&reference.uri)
+            || (is_remote
+                && self
+                    .http
+                    .content_type(&reference.uri)
+                    .is_some_and(|media_type| MARKUP_TYPES.contains(&media_type.as_str())));
+        let sanitize = if is_remote {
+            self.config.sanitize.remote
+        } else {
+            self.config.sanitize.local
+        };
+        let result = if is_markup && sanitize {
+            self.sanitize(&result, &reference.uri)?
+        } else {
+            result
+        };
+
+        self.cache
+            .insert(cache_key, (result.clone(), resolved_path.clone()));
         Ok((result, resolved_path))
     }
 
-    fn fetch_http(&self, uri: &str) -> Result<String> {
-        let response =
-            reqwest::blocking::get(uri).with_context(|| format!("Failed to fetch {}", uri))?;
-
-        if !response.status().is_success() {
-            return Err(anyhow!("HTTP {} for {}", response.status(), uri));
+    /// Strip what could run script from content about to be rendered as markup
+    /// Whatever was removed is reported as a warning naming the source.
+    pub fn sanitize(&mut self, content: &str, uri: &str) -> Result<String> {
+        let (clean, mut removed) = crate::sanitize::sanitize_html(content, &self.config.sanitize)?;
+        let (clean, links) = crate::sanitize::sanitize_markdown_links(&clean);
+        removed.extend(links);
+        if !removed.is_empty() {
+            self.warnings.push(format!(
+                "Sanitized {}: removed {}",
+                http::redact_url(uri),
+                removed.join(", ")
+            ));
         }
+        Ok(clean)
+    }
 
-        response.text().context("Failed to read response body")
+    /// Fetch a remote source through the on-disk cache
+    fn fetch_http(&mut self, reference: &Reference) -> Result<String> {
+        // Top-level references were checked up front; this catches nested ones
+        policy::check_url(&reference.uri, &self.config.network).map_err(|reason| {
+            anyhow!(
+                "{} is not allowed by the network policy ({})",
+                reference.display_uri(),
+                reason
+            )
+        })?;
+        let body = match self.prefetched.get_mut(&reference.uri) {
+            Some(fetched) => {
+                self.warnings.append(&mut fetched.warnings);
+                fetched.body.clone().map_err(|e| anyhow!(e))?
+            }
+            None => {
+                let ttl =
+                    http::ttl_for(&reference.uri, reference.option("ttl"), &self.config.cache)?;
+                let refresh = self.lockfile.wants_update(&reference.uri);
+                self.http
+                    .fetch(&reference.uri, ttl, refresh, &mut self.warnings)?
+            }
+        };
+        self.lockfile.verify(&reference.uri, &body)?;
+        Ok(body)
     }
 
-    fn fetch_local(&self, path: &str, current_file_path: Option<&str>) -> Result<(String, String)> {
-        // Reject paths that try to escape the repo
-        if path.contains("..") {
-            return Err(anyhow!("Path contains '..' which is not allowed: {}", path));
-        }
-
-        // Try file-relative first if we have a current file
-        if let Some(current) = current_file_path {
-            let current_dir = std::path::Path::new(current).parent();
-            if let Some(dir) = current_dir {
-                let file_relative = self.repo_root.join(dir).join(path);
-                if file_relative.exists() && file_relative.starts_with(&self.repo_root) {
-                    let content = std::fs::read_to_string(&file_relative)
-                        .with_context(|| format!("Failed to read file: {}", path))?;
-                    // Return the repo-relative path
-                    let resolved = file_relative
-                        .strip_prefix(&self.repo_root)
-                        .unwrap_or(&file_relative)
-                        .to_string_lossy()
-                        .to_string();
-                    return Ok((content, resolved));
+    /// True if the reference gathers several items: a glob in a local path, or
+    /// a `*` wildcard in a plaintext id
+    /// A path with glob characters is a single file if one has exactly that name.
+    pub fn is_multi(&self, reference: &Reference, current_file_path: Option<&str>) -> bool {
+        reference.has_wildcard_id() || self.is_glob(reference, current_file_path)
+    }
+
+    fn is_glob(&self, reference: &Reference, current_file_path: Option<&str>) -> bool {
+        reference.has_glob_path()
+            && !self
+                .locate_local(&reference.uri, current_file_path)
+                .is_ok_and(|(full_path, _)| full_path.is_file())
+    }
+
+    /// Expand a glob path and/or wildcard id into one reference per item
+    /// Files are sorted by path; ids keep their order within each file.
+    pub fn expand(
+        &mut self,
+        reference: &Reference,
+        current_file_path: Option<&str>,
+    ) -> Result<Vec<Reference>> {
+        let paths = if self.is_glob(reference, current_file_path) {
+            if reference.rev.is_some() {
+                return Err(anyhow!(
+                    "Glob paths can't be combined with a revision: {}",
+                    reference.display_uri()
+                ));
+            }
+            self.glob_paths(&reference.uri, current_file_path)?
+        } else {
+            vec![reference.uri.clone()]
+        };
+
+        let wildcard = reference.selector.as_deref().filter(|s| s.contains('*'));
+        let mut items = Vec::new();
+        for path in paths {
+            let item = Reference {
+                uri: path,
+                ..reference.clone()
+            };
+            let Some(pattern) = wildcard else {
+                items.push(item);
+                continue;
+            };
+
+            // Read the whole file to find its ids
+            let whole = Reference {
+                uri: item.uri.clone(),
+                selector: None,
+                transform: None,
+                indent_override: None,
+                rev: item.rev.clone(),
+                options: BTreeMap::new(),
+            };
+            let (content, resolved_path) = self.resolve(&whole, current_file_path)?;
+            let parser =
+                crate::plaintext::PlaintextParser::new(std::path::Path::new(&resolved_path));
+            for block in parser.parse(&content)? {
+                if let crate::plaintext::Block::Id { id, .. } = block
+                    && wildcard_match(pattern, &id)
+                {
+                    items.push(Reference {
+                        selector: Some(id),
+                        ..item.clone()
+                    });
                 }
             }
         }
 
-        // Fall back to repo-relative
-        let full_path = self.repo_root.join(path);
+        if items.is_empty() {
+            return Err(anyhow!("Nothing matches {}", reference.display_uri()));
+        }
+        Ok(items)
+    }
 
-        // Verify the resolved path is still within repo
-        if !full_path.starts_with(&self.repo_root) {
-            return Err(anyhow!("Path escapes repository: {}", path));
+    /// Files matching a glob, tried file-relative first and then repo-relative
+    /// Returned paths are relative to whichever base matched, like plain paths.
+    fn glob_paths(&self, pattern: &str, current_file_path: Option<&str>) -> Result<Vec<String>> {
+        // A pattern with `..` or a leading `/` has one place to look, so its
+        // matches are anchored at the root to be found there again
+        let climbs = std::path::Path::new(pattern)
+            .components()
+            .any(|c| c == std::path::Component::ParentDir);
+        if climbs || pattern.starts_with('/') {
+            let candidates = sandbox::candidates(pattern, host_dir(current_file_path).as_deref())?;
+            let paths = self.glob_files(&self.repo_root.join(&candidates[0]), &self.repo_root)?;
+            return Ok(paths.iter().map(|path| format!("/{}", path)).collect());
         }
 
-        let content = std::fs::read_to_string(&full_path)
-            .with_context(|| format!("Failed to read file: {}", path))?;
-        Ok((content, path.to_string()))
+        let mut bases = Vec::new();
+        if let Some(dir) = current_file_path.and_then(|c| std::path::Path::new(c).parent())
+            && !dir.as_os_str().is_empty()
+        {
+            bases.push(self.repo_root.join(dir));
+        }
+        bases.push(self.repo_root.clone());
+
+        for base in bases {
+            let paths = self.glob_files(&base.join(pattern), &base)?;
+            if !paths.is_empty() {
+                return Ok(paths);
+            }
+        }
+
+        Ok(Vec::new())
     }
 
-    fn fetch_local_binary(
+    /// Files matching a full glob pattern, sorted, as paths relative to `base`
+    fn glob_files(
+        &self,
+        full_pattern: &std::path::Path,
+        base: &std::path::Path,
+    ) -> Result<Vec<String>> {
+        let full_pattern = full_pattern
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid path in glob pattern: {}", full_pattern.display()))?;
+        let mut paths: Vec<String> = glob::glob(full_pattern)?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .filter_map(|path| {
+                path.strip_prefix(base)
+                    .ok()
+                    .map(|p| p.to_string_lossy().to_string())
+            })
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Resolve every item of a multi reference and join them
+    fn resolve_multi(
+        &mut self,
+        reference: &Reference,
+        current_file_path: Option<&str>,
+    ) -> Result<(String, String)> {
+        let mut resolved = Vec::new();
+        for item in self.expand(reference, current_file_path)? {
+            let (content, resolved_path) = self.resolve(&item, current_file_path)?;
+            resolved.push((item, content, resolved_path));
+        }
+
+        // Items share a type, so the first one's path stands in for the set
+        let first_path = resolved
+            .first()
+            .map(|(_, _, path)| path.clone())
+            .unwrap_or_default();
+        Ok((join_items(reference, &resolved), first_path))
+    }
+
+    /// Resolve both sides of a `diff:` spec and diff them
+    /// Returns the unified diff text; the path is the spec itself, which matches no file type
+    fn resolve_diff(
+        &mut self,
+        spec: &str,
+        current_file_path: Option<&str>,
+    ) -> Result<(String, String)> {
+        let (old, new) = diff::parse_spec(spec)?;
+        let (old_content, _) = self.resolve(&old, current_file_path)?;
+        let (new_content, _) = self.resolve(&new, current_file_path)?;
+        let unified = diff::unified(
+            &old_content,
+            &new_content,
+            &diff::label(&old),
+            &diff::label(&new),
+        );
+        Ok((unified, format!("{}{}", DIFF_SCHEME, spec)))
+    }
+
+    /// True if the `@` in `uri@rev` is part of a file name rather than a revision pin:
+    /// a file has the whole name, or neither the path nor the revision exists
+    /// (`docs/user@example.com.md`)
+    fn is_literal_at_path(&self, reference: &Reference, current_file_path: Option<&str>) -> bool {
+        let Some(rev) = &reference.rev else {
+            return false;
+        };
+        let is_file = |path: &str| {
+            self.locate_local(path, current_file_path)
+                .is_ok_and(|(full_path, _)| full_path.is_file())
+        };
+        is_file(&format!("{}@{}", reference.uri, rev))
+            || (!is_file(&reference.uri) && self.rev_parse(rev).is_err())
+    }
+
+    /// Read a file as it was at a git revision, from the object database
+    fn fetch_git(
         &self,
         path: &str,
+        rev: &str,
         current_file_path: Option<&str>,
+        binary: bool,
     ) -> Result<(String, String)> {
-        // Reject paths that try to escape the repo
-        if path.contains("..") {
-            return Err(anyhow!("Path contains '..' which is not allowed: {}", path));
-        }
-
-        // Try file-relative first if we have a current file
-        if let Some(current) = current_file_path {
-            let current_dir = std::path::Path::new(current).parent();
-            if let Some(dir) = current_dir {
-                let file_relative = self.repo_root.join(dir).join(path);
-                if file_relative.exists() && file_relative.starts_with(&self.repo_root) {
-                    let bytes = std::fs::read(&file_relative)
-                        .with_context(|| format!("Failed to read file: {}", path))?;
-                    let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
-                    // Return the repo-relative path
-                    let resolved = file_relative
-                        .strip_prefix(&self.repo_root)
-                        .unwrap_or(&file_relative)
-                        .to_string_lossy()
-                        .to_string();
-                    return Ok((encoded, resolved));
-                }
+        // Try file-relative first, then repo-relative, as for working-tree files
+        let candidates: Vec<String> =
+            sandbox::candidates(path, host_dir(current_file_path).as_deref())?
+                .iter()
+                .map(|candidate| candidate.to_string_lossy().to_string())
+                .collect();
+
+        let mut last_error = String::new();
+        for candidate in &candidates {
+            let output = Command::new("git")
+                .args(["cat-file", "blob", &format!("{}:{}", rev, candidate)])
+                .current_dir(&self.repo_root)
+                .output()
+                .context("Failed to run git cat-file")?;
+            if output.status.success() {
+                let content = if binary {
+                    base64::engine::general_purpose::STANDARD.encode(&output.stdout)
+                } else {
+                    String::from_utf8(output.stdout)
+                        .with_context(|| format!("{}@{} is not valid UTF-8", path, rev))?
+                };
+                return Ok((content, candidate.clone()));
             }
+            last_error = String::from_utf8_lossy(&output.stderr).trim().to_string();
         }
 
-        // Fall back to repo-relative
-        let full_path = self.repo_root.join(path);
+        Err(anyhow!(
+            "Failed to read {} at revision {}: {} (write an @ that is part of a file name as %40)",
+            path,
+            rev,
+            last_error
+        ))
+    }
 
-        // Verify the resolved path is still within repo
-        if !full_path.starts_with(&self.repo_root) {
-            return Err(anyhow!("Path escapes repository: {}", path));
+    /// Find a local file: file-relative first, then repo-relative
+    /// Returns the full path and the repo-relative path
+    fn locate_local(
+        &self,
+        path: &str,
+        current_file_path: Option<&str>,
+    ) -> Result<(PathBuf, String)> {
+        let candidates = sandbox::candidates(path, host_dir(current_file_path).as_deref())?;
+        for candidate in &candidates {
+            if let Some(full_path) = self.sandbox.confine(candidate)? {
+                return Ok((full_path, candidate.to_string_lossy().to_string()));
+            }
         }
 
+        // A missing file is reported when it's read
+        let last = candidates.last().expect("at least one candidate");
+        Ok((
+            self.repo_root.join(last),
+            last.to_string_lossy().to_string(),
+        ))
+    }
+
+    fn fetch_local(&self, path: &str, current_file_path: Option<&str>) -> Result<(String, String)> {
+        let (full_path, resolved) = self.locate_local(path, current_file_path)?;
+        let content = std::fs::read_to_string(&full_path)
+            .with_context(|| format!("Failed to read file: {}", path))?;
+        Ok((content, resolved))
+    }
+
+    fn fetch_local_binary(
+        &self,
+        path: &str,
+        current_file_path: Option<&str>,
+    ) -> Result<(String, String)> {
+        let (full_path, resolved) = self.locate_local(path, current_file_path)?;
         let bytes =
             std::fs::read(&full_path).with_context(|| format!("Failed to read file: {}", path))?;
         let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
-        Ok((encoded, path.to_string()))
+        Ok((encoded, resolved))
+    }
+
+    /// Read a file from a crate's local sources; never touches the network
+    fn fetch_crate(&mut self, spec: &str, binary: bool) -> Result<String> {
+        let (name, version, path) = crates::parse_spec(spec)?;
+        if self.crate_packages.is_none() {
+            self.crate_packages = Some(crates::metadata_packages(&self.repo_root));
+        }
+        let packages = self.crate_packages.as_deref().unwrap_or_default();
+        let dir = crates::locate(name, version, packages, crates::cargo_home().as_deref())?;
+
+        let file = crates::confine(&dir, path)?;
+        if binary {
+            let bytes = std::fs::read(&file)
+                .with_context(|| format!("Failed to read {} from crate {}", path, name))?;
+            Ok(base64::engine::general_purpose::STANDARD.encode(&bytes))
+        } else {
+            std::fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read {} from crate {}", path, name))
+        }
+    }
+
+    /// Extract text or cells from a local office document
+    /// Renders the `?format=` given (Markdown by default).
+    fn resolve_office(
+        &self,
+        reference: &Reference,
+        current_file_path: Option<&str>,
+    ) -> Result<(String, String)> {
+        if is_remote_uri(&reference.uri) || reference.rev.is_some() {
+            return Err(anyhow!(
+                "Office documents can only be read from the working tree: {}",
+                reference.display_uri()
+            ));
+        }
+        let (full_path, resolved) = self.locate_local(&reference.uri, current_file_path)?;
+        let bytes = std::fs::read(&full_path)
+            .with_context(|| format!("Failed to read file: {}", reference.uri))?;
+        let format = Format::parse(reference.option("format"))?;
+        let content = office::extract(
+            &bytes,
+            &reference.uri,
+            reference.selector.as_deref(),
+            format,
+        )?;
+        Ok((content, resolved))
+    }
+
+    /// Run a `?sql=` or named `?query=` against a local SQLite database
+    fn resolve_database(
+        &self,
+        reference: &Reference,
+        current_file_path: Option<&str>,
+    ) -> Result<(String, String)> {
+        if is_remote_uri(&reference.uri) || reference.rev.is_some() {
+            return Err(anyhow!(
+                "Databases can only be read from the working tree: {}",
+                reference.display_uri()
+            ));
+        }
+        let sql = match (reference.option("sql"), reference.option("query")) {
+            (Some(sql), None) => sql,
+            (None, Some(name)) => self
+                .config
+                .sql
+                .queries
+                .get(name)
+                .ok_or_else(|| anyhow!("No query named '{}' in [sql.queries]", name))?,
+            _ => {
+                return Err(anyhow!(
+                    "Database references need either ?sql= or ?query=: {}",
+                    reference.uri
+                ));
+            }
+        };
+        let (full_path, resolved) = self.locate_local(&reference.uri, current_file_path)?;
+        if !full_path.is_file() {
+            return Err(anyhow!("Failed to read file: {}", reference.uri));
+        }
+        let format = Format::parse(reference.option("format"))?;
+        let rows = sqlite::query(&full_path, sql)?;
+        Ok((table::render(&rows, format), resolved))
+    }
+
+    /// Read a member of a zip or tar archive (`assets/starter.zip!/src/main.rs`)
+    fn fetch_archive_member(
+        &self,
+        archive_path: &str,
+        member: &str,
+        current_file_path: Option<&str>,
+        binary: bool,
+    ) -> Result<(String, String)> {
+        let (full_path, resolved) = self.locate_local(archive_path, current_file_path)?;
+        let bytes = std::fs::read(&full_path)
+            .with_context(|| format!("Failed to read file: {}", archive_path))?;
+        let data = archive::read_member(&bytes, archive_path, member)?;
+
+        let content = if binary {
+            base64::engine::general_purpose::STANDARD.encode(&data)
+        } else {
+            String::from_utf8(data)
+                .with_context(|| format!("{}!/{} is not valid UTF-8", archive_path, member))?
+        };
+        Ok((content, format!("{}!/{}", resolved, member)))
     }
 
     fn apply_transform(&self, content: &str, transform: &str, uri: &str) -> Result<String> {
         match transform {
             "dataurl" => {
-                // Determine MIME type from file extension
-                let mime = if uri.ends_with(".png") {
+                // Determine MIME type from the file's extension, not a query or fragment
+                let path = uri_path(uri).to_ascii_lowercase();
+                let mime = if path.ends_with(".png") {
                     "image/png"
-                } else if uri.ends_with(".jpg") || uri.ends_with(".jpeg") {
+                } else if path.ends_with(".jpg") || path.ends_with(".jpeg") {
                     "image/jpeg"
-                } else if uri.ends_with(".gif") {
+                } else if path.ends_with(".gif") {
                     "image/gif"
-                } else if uri.ends_with(".svg") {
+                } else if path.ends_with(".svg") {
                     "image/svg+xml"
-                } else if uri.ends_with(".webp") {
+                } else if path.ends_with(".webp") {
                     "image/webp"
                 } else {
                     "application/octet-stream"
@@ -262,8 +1233,118 @@ impl Resolver {
         }
     }
 
-    fn extract_content(&self, content: &str, uri: &str, selector: &str) -> Result<String> {
-        if uri.ends_with(".html") || uri.ends_with(".htm") {
+    /// Apply `?linenos` and `?permalink`, which need the snippet's source line range
+    fn decorate_lines(
+        &mut self,
+        content: String,
+        lines: Option<(usize, usize)>,
+        reference: &Reference,
+        resolved_path: &str,
+        revision: Option<&str>,
+    ) -> Result<String> {
+        let wants_linenos = reference.has_option("linenos");
+        let permalink = reference.option("permalink");
+        if !wants_linenos && permalink.is_none() {
+            return Ok(content);
+        }
+
+        let (start, end) = lines.ok_or_else(|| {
+            anyhow!(
+                "?linenos and ?permalink need a plaintext id block or a whole file: {}",
+                reference.uri
+            )
+        })?;
+
+        let url = match permalink {
+            Some(_) => {
+                // `{path}` is a path in this repository, which only a plain file has
+                let in_repository = !is_remote_uri(&reference.uri)
+                    && !reference.uri.starts_with(CRATE_SCHEME)
+                    && !reference.uri.contains(archive::MEMBER_SEPARATOR);
+                if !in_repository {
+                    return Err(anyhow!(
+                        "?permalink needs a file in this repository: {}",
+                        reference.display_uri()
+                    ));
+                }
+                Some(self.permalink_url(resolved_path, revision, start, end)?)
+            }
+            None => None,
+        };
+
+        // `?permalink=url` makes the link itself the content (e.g. for href-transclude)
+        if let (Some("url"), Some(url)) = (permalink, &url) {
+            return Ok(url.clone());
+        }
+
+        let mut result = if wants_linenos {
+            crate::plaintext::number_lines(&content, start)
+        } else {
+            content
+        };
+        if let Some(url) = url {
+            let parser =
+                crate::plaintext::PlaintextParser::new(std::path::Path::new(resolved_path));
+            result.push('\n');
+            result.push_str(&parser.comment(&format!("Source: {}", url)));
+        }
+        Ok(result)
+    }
+
+    /// Build a source link from the `[permalink]` template in `.liaison.toml`
+    /// Links point at the pinned revision if there is one, otherwise at HEAD
+    fn permalink_url(
+        &mut self,
+        path: &str,
+        revision: Option<&str>,
+        start: usize,
+        end: usize,
+    ) -> Result<String> {
+        let template = self.config.permalink.url.clone().ok_or_else(|| {
+            anyhow!("?permalink needs a [permalink] url template in .liaison.toml")
+        })?;
+
+        let commit = match revision {
+            Some(rev) => self.rev_parse(rev)?,
+            None => {
+                if self.head_commit.is_none() {
+                    self.head_commit = Some(self.rev_parse("HEAD")?);
+                }
+                self.head_commit.clone().unwrap_or_default()
+            }
+        };
+
+        Ok(template
+            .replace("{commit}", &commit)
+            .replace("{path}", path)
+            .replace("{start}", &start.to_string())
+            .replace("{end}", &end.to_string()))
+    }
+
+    /// Resolve a revision to its full commit id
+    fn rev_parse(&self, rev: &str) -> Result<String> {
+        let output = Command::new("git")
+            .args(["rev-parse", "--verify", &format!("{}^{{commit}}", rev)])
+            .current_dir(&self.repo_root)
+            .output()
+            .context("Failed to run git rev-parse")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Unknown git revision '{}': {}",
+                rev,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    }
+
+    fn extract_content(
+        &self,
+        content: &str,
+        uri: &str,
+        selector: &str,
+    ) -> Result<(String, Option<(usize, usize)>)> {
+        if is_html_uri(uri) {
             // For HTML, if the selector is just a simple ID (no # prefix), add it
             let css_selector = if !selector.starts_with('#')
                 && !selector.starts_with('.')
@@ -275,17 +1356,28 @@ impl Resolver {
             } else {
                 selector.to_string()
             };
-            crate::html::extract_by_selector(content, &css_selector)
+            Ok((
+                crate::html::extract_by_selector(content, &css_selector)?,
+                None,
+            ))
+        } else if let Some((start, end)) = crate::plaintext::parse_line_range(selector) {
+            let text = crate::plaintext::extract_lines(content, uri, start, end)?;
+            Ok((text, Some((start, end))))
         } else {
-            crate::plaintext::extract_by_id(content, uri, selector)
+            let (text, lines) = crate::plaintext::extract_by_id(content, uri, selector)?;
+            Ok((text, Some(lines)))
         }
     }
 
-    fn extract_default(&self, content: &str, uri: &str) -> Result<String> {
-        if uri.ends_with(".html") || uri.ends_with(".htm") {
-            crate::html::extract_by_selector(content, "body")
+    fn extract_default(
+        &self,
+        content: &str,
+        uri: &str,
+    ) -> Result<(String, Option<(usize, usize)>)> {
+        if is_html_uri(uri) {
+            Ok((crate::html::extract_by_selector(content, "body")?, None))
         } else {
-            Ok(content.to_string())
+            Ok((content.to_string(), Some((1, content.lines().count()))))
         }
     }
 }
@@ -324,6 +1416,7 @@ impl CycleDetector {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_reference_parse_basic() {
@@ -379,4 +1472,659 @@ mod tests {
         assert_eq!(r.transform, Some("dataurl".to_string()));
         assert_eq!(r.indent_override, Some(false));
     }
+
+    #[test]
+    fn test_reference_parse_key_value_option() {
+        let r = Reference::parse("guide.md#intro?shift-headings=2").unwrap();
+        assert_eq!(r.transform, None);
+        assert_eq!(r.option("shift-headings"), Some("2"));
+    }
+
+    #[test]
+    fn test_reference_parse_options() {
+        let r = Reference::parse("guide.md#intro?norewrite&noindent").unwrap();
+        assert_eq!(r.uri, "guide.md");
+        assert_eq!(r.transform, None);
+        assert!(r.has_option("norewrite"));
+        assert_eq!(r.option("norewrite"), Some(""));
+        assert_eq!(r.indent_override, Some(false));
+    }
+
+    #[test]
+    fn test_resolve_sanitizes_local_html_when_enabled() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut config = Config::default();
+        config.sanitize.local = true;
+        config.sanitize.tags = vec!["p".to_string()];
+
+        let mut resolver = Resolver::new(repo_root, config);
+        let reference = Reference::parse("tests/fixtures/source.html#intro").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+
+        assert!(content.contains("<p>Welcome to the guide</p>"));
+        assert!(resolver.take_warnings().is_empty());
+
+        let reference = Reference::parse("tests/fixtures/form.html#field").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert!(!content.contains("<label"));
+        let warnings = resolver.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("<label> tag (1)"));
+    }
+
+    #[test]
+    fn test_resolve_linenos() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+        let reference = Reference::parse("tests/fixtures/source.rs#helper?linenos").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(content, "2 | fn helper() -> i32 {\n3 |     42\n4 | }");
+    }
+
+    #[test]
+    fn test_resolve_permalink() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut config = Config::default();
+        config.permalink.url =
+            Some("https://forge.test/{commit}/{path}#L{start}-L{end}".to_string());
+        let mut resolver = Resolver::new(repo_root, config);
+        resolver.head_commit = Some("abc123".to_string());
+
+        let reference = Reference::parse("tests/fixtures/source.rs#helper?permalink").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert!(
+            content.ends_with(
+                "}\n// Source: https://forge.test/abc123/tests/fixtures/source.rs#L2-L4"
+            )
+        );
+
+        let reference = Reference::parse("tests/fixtures/source.rs#helper?permalink=url").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(
+            content,
+            "https://forge.test/abc123/tests/fixtures/source.rs#L2-L4"
+        );
+    }
+
+    #[test]
+    fn test_dataurl_mime_ignores_query_and_fragment() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let resolver = Resolver::new(repo_root, Config::default());
+        assert_eq!(
+            resolver
+                .apply_transform("AA==", "dataurl", "https://x.org/logo.PNG?v=2#top")
+                .unwrap(),
+            "data:image/png;base64,AA=="
+        );
+        assert_eq!(
+            resolver
+                .apply_transform("AA==", "dataurl", "https://x.org/logo.svg?format=png")
+                .unwrap(),
+            "data:image/svg+xml;base64,AA=="
+        );
+    }
+
+    #[test]
+    fn test_resolve_permalink_requires_template() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+        let reference = Reference::parse("tests/fixtures/source.rs#helper?permalink").unwrap();
+        let err = resolver.resolve(&reference, None).unwrap_err();
+        assert!(err.to_string().contains("[permalink]"));
+    }
+
+    #[test]
+    fn test_resolve_archive_member() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("liaison-archive-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        let file = std::fs::File::create(dir.join("assets/starter.zip")).unwrap();
+        let mut writer = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default();
+        writer.start_file("src/main.rs", options).unwrap();
+        writer
+            .write_all(b"// liaison id=setup\nlet app = App::new();\n// liaison end\n")
+            .unwrap();
+        writer.start_file("logo.png", options).unwrap();
+        writer.write_all(&[0x89, b'P', b'N', b'G']).unwrap();
+        writer.finish().unwrap();
+
+        let mut resolver = Resolver::new(dir.clone(), Config::default());
+        let reference = Reference::parse("assets/starter.zip!/src/main.rs#setup").unwrap();
+        let (content, path) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(content, "let app = App::new();");
+        assert_eq!(path, "assets/starter.zip!/src/main.rs");
+
+        // File-relative archive path, binary member
+        let reference = Reference::parse("starter.zip!/logo.png?dataurl").unwrap();
+        let (content, _) = resolver
+            .resolve(&reference, Some("assets/index.md"))
+            .unwrap();
+        assert_eq!(content, "data:image/png;base64,iVBORw==");
+
+        let escape = Reference::parse("assets/starter.zip!/../secret.txt").unwrap();
+        assert!(resolver.resolve(&escape, None).is_err());
+
+        // A member has no path in the repository to link to
+        let mut config = Config::default();
+        config.permalink.url = Some("https://forge.test/{commit}/{path}".to_string());
+        let mut resolver = Resolver::new(dir.clone(), config);
+        let reference =
+            Reference::parse("assets/starter.zip!/src/main.rs#setup?permalink").unwrap();
+        let err = resolver.resolve(&reference, None).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("?permalink needs a file in this repository")
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_line_range() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+
+        let reference = Reference::parse("tests/fixtures/source.rs#L2-L4?linenos").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(content, "2 | fn helper() -> i32 {\n3 |     42\n4 | }");
+
+        let reference = Reference::parse("tests/fixtures/source.rs#L40-L41").unwrap();
+        assert!(resolver.resolve(&reference, None).is_err());
+    }
+
+    #[test]
+    fn test_resolve_workspace_crate() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+
+        let reference = Reference::parse("crate:liaison/tests/fixtures/source.rs#L7").unwrap();
+        assert_eq!(reference.rev, None);
+        let (content, path) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(content, "fn main() {");
+        assert_eq!(path, "crate:liaison/tests/fixtures/source.rs");
+    }
+
+    #[test]
+    fn test_resolve_glob_with_headers() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+
+        let reference = Reference::parse("examples/*.rs#usage?header").unwrap();
+        assert!(resolver.is_multi(&reference, Some("tests/fixtures/guide.md")));
+        let (content, _) = resolver
+            .resolve(&reference, Some("tests/fixtures/guide.md"))
+            .unwrap();
+        assert_eq!(
+            content,
+            "// tests/fixtures/examples/advanced.rs#usage\n\
+             let client = Client::builder().retries(3).build();\n\n\
+             // tests/fixtures/examples/basic.rs#usage\n\
+             let client = Client::new();"
+        );
+    }
+
+    #[test]
+    fn test_resolve_wildcard_ids() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+
+        let reference =
+            Reference::parse("tests/fixtures/tutorial.rs#step-*?separator=\\n&header={id}:")
+                .unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(content, "step-1:\nlet x = 1;\nstep-2:\nlet z = x + 1;");
+
+        let none = Reference::parse("tests/fixtures/tutorial.rs#missing-*").unwrap();
+        assert!(resolver.resolve(&none, None).is_err());
+
+        // In HTML sources `*` is a CSS selector, not a wildcard
+        let css = Reference::parse("page.html#nav > *").unwrap();
+        assert!(!resolver.is_multi(&css, None));
+    }
+
+    #[test]
+    fn test_resolve_literal_bracket_path() {
+        let dir = std::env::temp_dir().join(format!("liaison-brackets-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes[1].md"), "literal").unwrap();
+        std::fs::write(dir.join("notes1.md"), "matched").unwrap();
+
+        // The file by that exact name wins over the glob it also spells
+        let mut resolver = Resolver::new(dir.clone(), Config::default());
+        let reference = Reference::parse("notes[1].md").unwrap();
+        assert!(!resolver.is_multi(&reference, None));
+        assert_eq!(resolver.resolve(&reference, None).unwrap().0, "literal");
+
+        std::fs::remove_file(dir.join("notes[1].md")).unwrap();
+        let mut resolver = Resolver::new(dir.clone(), Config::default());
+        assert!(resolver.is_multi(&reference, None));
+        assert_eq!(resolver.resolve(&reference, None).unwrap().0, "matched");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_metadata() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+
+        let reference = Reference::parse("cargo:package.name").unwrap();
+        let (content, _) = resolver.resolve(&reference, Some("README.md")).unwrap();
+        assert_eq!(content, "liaison");
+
+        let reference = Reference::parse("npm:name@next").unwrap();
+        assert_eq!(reference.uri, "npm:name@next");
+        assert_eq!(reference.rev, None);
+    }
+
+    #[test]
+    fn test_resolve_command_needs_allowlist() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let reference = Reference::parse("cmd:echo '#1 @home'?noindent").unwrap();
+        assert_eq!(reference.uri, "cmd:echo '#1 @home'");
+        assert_eq!(reference.selector, None);
+
+        let mut resolver = Resolver::new(repo_root.clone(), Config::default());
+        assert!(resolver.resolve(&reference, None).is_err());
+
+        let mut config = Config::default();
+        config.cmd.allow = vec!["echo '#1 @home'".to_string()];
+        let mut resolver = Resolver::new(repo_root, config);
+        let (content, _) = resolver.resolve(&reference, Some("docs/a.md")).unwrap();
+        assert_eq!(content, "#1 @home\n");
+    }
+
+    #[test]
+    fn test_reference_parse_url_query() {
+        let r = Reference::parse("https://api.example.com/doc?version=2#intro?noindent").unwrap();
+        assert_eq!(r.uri, "https://api.example.com/doc?version=2");
+        assert_eq!(r.selector.as_deref(), Some("intro"));
+        assert_eq!(r.indent_override, Some(false));
+
+        let r = Reference::parse("https://api.example.com/doc?version=2#").unwrap();
+        assert_eq!(r.uri, "https://api.example.com/doc?version=2");
+        assert_eq!(r.selector, None);
+        assert!(r.options.is_empty());
+
+        // Without a `#`, the `?` starts liaison's own parameters
+        let r = Reference::parse("https://api.example.com/doc?linenos").unwrap();
+        assert_eq!(r.uri, "https://api.example.com/doc");
+        assert!(r.has_option("linenos"));
+    }
+
+    #[test]
+    fn test_reference_parse_escapes() {
+        let r = Reference::parse("notes/a%23b%3F.md%40v1@main#q%3F?header=x%26y&sep%3D=1").unwrap();
+        assert_eq!(r.uri, "notes/a#b?.md@v1");
+        assert_eq!(r.rev.as_deref(), Some("main"));
+        assert_eq!(r.selector.as_deref(), Some("q?"));
+        assert_eq!(r.option("header"), Some("x&y"));
+        assert_eq!(r.option("sep="), Some("1"));
+        assert_eq!(Reference::parse("v1.%2E2.txt").unwrap().uri, "v1..2.txt");
+
+        // A `%` that isn't one of the escapes is literal
+        assert_eq!(
+            Reference::parse("cmd:printf %s").unwrap().uri,
+            "cmd:printf %s"
+        );
+        // URLs keep their own percent-encoding
+        assert_eq!(
+            Reference::parse("https://x.org/a%23b.md").unwrap().uri,
+            "https://x.org/a%23b.md"
+        );
+    }
+
+    #[test]
+    fn test_reference_display() {
+        for canonical in [
+            "src/lib.rs#helper",
+            "src/lib.rs@v1.0#helper?noindent&linenos&sha256=ab12",
+            "examples/*.rs#usage?header=### {name}",
+            "https://api.example.com/doc?version=2#intro?indent",
+            "https://api.example.com/doc?version=2#",
+            "https://example.com/a.rs?rev=main",
+            "cmd:git log -1 --format=%h?lang=text",
+            "notes/a%23b.md#x%3F?html&as=rust",
+        ] {
+            let reference = Reference::parse(canonical).unwrap();
+            assert_eq!(reference.to_string(), canonical);
+        }
+        let r = Reference::parse("src/lib.rs#x?linenos&noindent&rev=v1").unwrap();
+        assert_eq!(r.to_string(), "src/lib.rs@v1#x?noindent&linenos");
+    }
+
+    fn arb_reference() -> impl Strategy<Value = Reference> {
+        let target = prop_oneof![
+            "[a-z0-9 ./_*#?@&=%-]{0,16}",
+            "https://[a-z]{1,8}\\.example/[a-z0-9/._%@-]{0,12}(\\?[a-z0-9=&%?]{0,8})?",
+            "crate:[a-z0-9/._#?@&%-]{1,12}",
+            "cargo:[a-z._#?@%]{1,10}",
+        ];
+        let verbatim = prop_oneof![
+            "cmd:[a-z #?@&=%-]{0,12}",
+            "diff:[a-z./#@%]{1,8}\\.\\.[a-z0-9]{0,4}"
+        ];
+        let transform = "[a-z0-9&=%#?-]{1,8}".prop_filter("not a flag", |t| {
+            t != "indent" && t != "noindent" && !OPTION_FLAGS.contains(&t.as_str())
+        });
+        let options = proptest::collection::btree_map(
+            prop_oneof![
+                "[a-z0-9&=%#?-]{0,6}",
+                proptest::sample::select(OPTION_FLAGS).prop_map(str::to_string)
+            ],
+            prop_oneof!["[a-z0-9&=%#? -]{0,6}", Just(String::new())],
+            0..4,
+        )
+        .prop_map(|mut options| {
+            // `rev=` is always read into the revision
+            options.remove("rev");
+            options
+        });
+        let selector = "[a-z0-9 #?@&=%*:!-]{1,10}";
+
+        (
+            prop_oneof![
+                (target, proptest::option::of(selector)),
+                (verbatim, Just(None))
+            ],
+            proptest::option::of(transform),
+            proptest::option::of(any::<bool>()),
+            proptest::option::of("[a-z0-9#?@&=%.]{0,6}"),
+            options,
+        )
+            .prop_map(
+                |((uri, selector), transform, indent_override, rev, options)| Reference {
+                    uri,
+                    selector,
+                    transform,
+                    indent_override,
+                    rev,
+                    options,
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn test_reference_display_round_trip(reference in arb_reference()) {
+            let text = reference.to_string();
+            prop_assert_eq!(Reference::parse(&text).unwrap(), reference, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_reference_relative_to_url() {
+        let base = "https://docs.example.com/guide/intro.md";
+        let resolve = |s: &str| Reference::parse(s).unwrap().relative_to_url(base);
+
+        let sibling = resolve("setup.md#install?noindent").unwrap();
+        assert_eq!(sibling.uri, "https://docs.example.com/guide/setup.md");
+        assert_eq!(sibling.selector.as_deref(), Some("install"));
+        assert_eq!(sibling.indent_override, Some(false));
+        assert_eq!(
+            resolve("../api/index.html").unwrap().uri,
+            "https://docs.example.com/api/index.html"
+        );
+        assert_eq!(
+            resolve("/etc/passwd").unwrap().uri,
+            "https://docs.example.com/etc/passwd"
+        );
+        assert_eq!(
+            resolve("//cdn.example.net/x.md").unwrap().uri,
+            "https://cdn.example.net/x.md"
+        );
+        let literal = resolve("img/logo@2x.svg").unwrap();
+        assert_eq!(
+            (literal.uri.as_str(), literal.rev),
+            ("https://docs.example.com/guide/img/logo@2x.svg", None)
+        );
+        assert_eq!(
+            resolve("https://other.example/a.md").unwrap().uri,
+            "https://other.example/a.md"
+        );
+
+        for reaching_local in [
+            "file:///etc/passwd",
+            "cmd:git log",
+            "cargo:package.version",
+            "crate:serde/src/lib.rs",
+            "diff:a.md..b.md",
+        ] {
+            assert!(resolve(reaching_local).is_err(), "{}", reaching_local);
+        }
+    }
+
+    #[test]
+    fn test_resolve_refuses_local_sources_for_remote_content() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+        let reference = Reference::parse("Cargo.toml").unwrap();
+        let err = resolver
+            .resolve(&reference, Some("https://example.com/docs/intro.md"))
+            .unwrap_err();
+        assert!(err.to_string().contains("can't reference local source"));
+    }
+
+    #[test]
+    fn test_resolve_html_url_with_query() {
+        let url = "https://docs.example.com/page.html?v=2";
+        let repo_root =
+            std::env::temp_dir().join(format!("liaison-html-url-{}", std::process::id()));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+        resolver.prefetched.insert(
+            url.to_string(),
+            Fetched {
+                body: Ok("<div id=\"intro\"><p>Hi</p><script>steal()</script></div>".to_string()),
+                warnings: Vec::new(),
+            },
+        );
+
+        // Read as HTML by its URL path, so the id is found and the script removed
+        let reference = Reference::parse(&format!("{}#intro", url)).unwrap();
+        assert_eq!(reference.uri, url);
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert!(content.contains("<p>Hi</p>"));
+        assert!(!content.contains("steal()"));
+        assert!(resolver.take_warnings()[0].starts_with("Sanitized"));
+    }
+
+    #[test]
+    fn test_resolve_sanitizes_remote_markup() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let repo_root =
+            std::env::temp_dir().join(format!("liaison-remote-markup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&repo_root);
+        let mut resolver = Resolver::new(repo_root.clone(), Config::default());
+
+        // Markdown with inline HTML, by its URL path
+        let markdown = "https://docs.example.com/intro.md";
+        resolver.prefetched.insert(
+            markdown.to_string(),
+            Fetched {
+                body: Ok("# Intro\n\n<img src=\"x.png\" onerror=\"steal()\">\n".to_string()),
+                warnings: Vec::new(),
+            },
+        );
+        let (content, _) = resolver
+            .resolve(&Reference::parse(markdown).unwrap(), None)
+            .unwrap();
+        assert_eq!(content, "# Intro\n\n<img src=\"x.png\">\n");
+        assert!(resolver.take_warnings()[0].contains("onerror attribute"));
+
+        // No extension, but the server says it's HTML
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/page", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let body = "<p>Hi</p><script>steal()</script>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        let (content, _) = resolver
+            .resolve(&Reference::parse(&url).unwrap(), None)
+            .unwrap();
+        server.join().unwrap();
+        assert_eq!(content, "<p>Hi</p>");
+        assert!(resolver.take_warnings()[0].contains("<script> element"));
+
+        std::fs::remove_dir_all(repo_root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_refuses_symlink_out_of_repo() {
+        let base = std::env::temp_dir().join(format!("liaison-symlink-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("repo/docs")).unwrap();
+        std::fs::create_dir_all(base.join("outside")).unwrap();
+        std::fs::write(base.join("outside/secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(base.join("outside"), base.join("repo/docs/etc")).unwrap();
+
+        let mut resolver = Resolver::new(base.join("repo"), Config::default());
+        let reference = Reference::parse("etc/secret.txt").unwrap();
+        let err = resolver
+            .resolve(&reference, Some("docs/guide.md"))
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("escapes the repository: docs/etc is a symlink to")
+        );
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    fn test_reference_parse_revision() {
+        let r = Reference::parse("src/lib.rs@v1.2#helper").unwrap();
+        assert_eq!(r.uri, "src/lib.rs");
+        assert_eq!(r.rev, Some("v1.2".to_string()));
+        assert_eq!(r.selector, Some("helper".to_string()));
+        assert_eq!(r.display_uri(), "src/lib.rs@v1.2");
+
+        let r = Reference::parse("src/lib.rs#helper?rev=HEAD~3").unwrap();
+        assert_eq!(r.uri, "src/lib.rs");
+        assert_eq!(r.rev, Some("HEAD~3".to_string()));
+        assert!(!r.has_option("rev"));
+
+        let r = Reference::parse("https://example.com/a@b#x").unwrap();
+        assert_eq!(r.uri, "https://example.com/a@b");
+        assert_eq!(r.rev, None);
+    }
+
+    /// Create a throwaway git repo with two commits of lib.rs, tagging the first as v1
+    fn git_fixture_repo(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("liaison-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "git {:?} failed: {:?}",
+                args,
+                output
+            );
+        };
+
+        git(&["init", "-q"]);
+        std::fs::write(
+            dir.join("src/lib.rs"),
+            "// liaison id=helper\nfn helper() -> i32 { 1 }\n// liaison end\n",
+        )
+        .unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "v1"]);
+        git(&["tag", "v1"]);
+        std::fs::write(
+            dir.join("src/lib.rs"),
+            "// liaison id=helper\nfn helper() -> i32 { 2 }\n// liaison end\n",
+        )
+        .unwrap();
+        git(&["commit", "-q", "-am", "v2"]);
+        dir
+    }
+
+    #[test]
+    fn test_resolve_at_revision() {
+        let repo = git_fixture_repo("rev");
+        let mut resolver = Resolver::new(repo.clone(), Config::default());
+
+        let current = Reference::parse("src/lib.rs#helper").unwrap();
+        let (content, _) = resolver.resolve(&current, None).unwrap();
+        assert_eq!(content, "fn helper() -> i32 { 2 }");
+
+        let tagged = Reference::parse("src/lib.rs@v1#helper").unwrap();
+        let (content, path) = resolver.resolve(&tagged, None).unwrap();
+        assert_eq!(content, "fn helper() -> i32 { 1 }");
+        assert_eq!(path, "src/lib.rs");
+
+        // File-relative lookup works inside the revision too
+        let relative = Reference::parse("lib.rs#helper?rev=HEAD~1").unwrap();
+        let (content, _) = resolver.resolve(&relative, Some("src/main.rs")).unwrap();
+        assert_eq!(content, "fn helper() -> i32 { 1 }");
+
+        let diff = Reference::parse("diff:src/lib.rs#helper@v1..").unwrap();
+        let (content, _) = resolver.resolve(&diff, None).unwrap();
+        assert_eq!(
+            content,
+            "--- src/lib.rs@v1#helper\n+++ src/lib.rs#helper\n@@ -1 +1 @@\n\
+             -fn helper() -> i32 { 1 }\n+fn helper() -> i32 { 2 }"
+        );
+
+        let missing = Reference::parse("src/lib.rs@nope#helper").unwrap();
+        let err = resolver.resolve(&missing, None).unwrap_err();
+        assert!(err.to_string().contains("at revision nope"));
+        assert!(err.to_string().contains("%40"));
+
+        std::fs::remove_dir_all(repo).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_at_sign_in_file_name() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+        let reference = Reference::parse("tests/fixtures/logo@2x.txt").unwrap();
+        assert_eq!(reference.rev, Some("2x.txt".to_string()));
+        let (content, path) = resolver.resolve(&reference, None).unwrap();
+        assert_eq!(content, "retina\n");
+        assert_eq!(path, "tests/fixtures/logo@2x.txt");
+
+        // Neither `user` nor a revision `example.com.md` exists, so the whole name is the file
+        let reference = Reference::parse("tests/fixtures/user@example.com.md").unwrap();
+        let err = resolver.resolve(&reference, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to read file: tests/fixtures/user@example.com.md"
+        );
+    }
+
+    #[test]
+    fn test_resolve_leaves_local_html_by_default() {
+        let repo_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut resolver = Resolver::new(repo_root, Config::default());
+        let reference = Reference::parse("tests/fixtures/form.html#field").unwrap();
+        let (content, _) = resolver.resolve(&reference, None).unwrap();
+        assert!(content.contains("<label for=\"email\">"));
+        assert!(resolver.take_warnings().is_empty());
+    }
 }
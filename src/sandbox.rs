@@ -0,0 +1,248 @@
+//! Keeping local references inside the repository
+//!
+//! A path is first normalized lexically: `..` may climb back out of the host
+//! file's directory, but never above the repository root. It is then walked
+//! one component at a time from the canonical repository root, so a symlink is
+//! judged by where it actually points rather than by how the path is spelled.
+//! `[paths] symlinks` decides which symlinks may be followed: none, those that
+//! stay inside the repository (the default), or all.
+
+use anyhow::{Result, anyhow};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::SymlinkPolicy;
+
+/// A path as written in a reference, made repository-relative without touching the filesystem
+/// `.` is dropped and `..` removes the previous component, starting from `base`
+/// (a repository-relative directory); a leading `/` starts from the repository
+/// root instead. Climbing above the root is refused.
+pub fn normalize(base: &Path, path: &str) -> Result<PathBuf> {
+    let mut normalized = base.to_path_buf();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    let from = if base.as_os_str().is_empty() || path.starts_with('/') {
+                        "the repository root".to_string()
+                    } else {
+                        base.display().to_string()
+                    };
+                    return Err(anyhow!(
+                        "Path {} escapes the repository: too many '..' from {}",
+                        path,
+                        from
+                    ));
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => normalized.clear(),
+        }
+    }
+    Ok(normalized)
+}
+
+/// Repository-relative paths a local reference may mean, in the order to try them
+/// A plain path is looked up next to the host file, then from the repository
+/// root. `/path` is only looked up from the root, and a path with `..` only
+/// next to the host file (or from the root when there is no host file).
+pub fn candidates(path: &str, host_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let root = Path::new("");
+    if path.starts_with('/') {
+        return Ok(vec![normalize(root, path)?]);
+    }
+    let climbs = Path::new(path)
+        .components()
+        .any(|c| c == Component::ParentDir);
+
+    let mut candidates = Vec::new();
+    if let Some(dir) = host_dir.filter(|dir| !dir.as_os_str().is_empty()) {
+        candidates.push(normalize(dir, path)?);
+    }
+    if !climbs || candidates.is_empty() {
+        let from_root = normalize(root, path)?;
+        if !candidates.contains(&from_root) {
+            candidates.push(from_root);
+        }
+    }
+    Ok(candidates)
+}
+
+pub struct Sandbox {
+    /// Canonical repository root
+    root: PathBuf,
+    symlinks: SymlinkPolicy,
+}
+
+impl Sandbox {
+    pub fn new(repo_root: &Path, symlinks: SymlinkPolicy) -> Self {
+        let root = repo_root
+            .canonicalize()
+            .unwrap_or_else(|_| repo_root.to_path_buf());
+        Self { root, symlinks }
+    }
+
+    /// The real location of a repository-relative path, or None if it doesn't exist
+    /// Fails naming the component at fault if the path goes through a symlink
+    /// the policy doesn't follow.
+    pub fn confine(&self, relative: &Path) -> Result<Option<PathBuf>> {
+        let mut current = self.root.clone();
+        let mut walked = PathBuf::new();
+        for component in relative.components() {
+            let Component::Normal(part) = component else {
+                return Err(anyhow!(
+                    "Path must be relative to the repository: {}",
+                    relative.display()
+                ));
+            };
+            walked.push(part);
+            let next = current.join(part);
+            let Ok(metadata) = std::fs::symlink_metadata(&next) else {
+                return Ok(None);
+            };
+            if !metadata.file_type().is_symlink() {
+                current = next;
+                continue;
+            }
+
+            if self.symlinks == SymlinkPolicy::Never {
+                return Err(anyhow!(
+                    "Path {} goes through a symlink at {}, which paths.symlinks = \"never\" doesn't follow",
+                    relative.display(),
+                    walked.display()
+                ));
+            }
+            // A dangling symlink reads as a missing file
+            let Ok(target) = next.canonicalize() else {
+                return Ok(None);
+            };
+            if self.symlinks == SymlinkPolicy::WithinRepo && !target.starts_with(&self.root) {
+                return Err(anyhow!(
+                    "Path {} escapes the repository: {} is a symlink to {}",
+                    relative.display(),
+                    walked.display(),
+                    target.display()
+                ));
+            }
+            current = target;
+        }
+        Ok(Some(current))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::os::unix::fs::symlink;
+
+    /// A repository with a file, a symlink to it, a symlinked directory inside
+    /// the repository and a symlink out of it
+    #[cfg(unix)]
+    fn fixture(name: &str) -> (PathBuf, PathBuf) {
+        let base =
+            std::env::temp_dir().join(format!("liaison-sandbox-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        let (repo, outside) = (base.join("repo"), base.join("outside"));
+        std::fs::create_dir_all(repo.join("docs")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(repo.join("docs/guide.md"), "guide").unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink("guide.md", repo.join("docs/alias.md")).unwrap();
+        symlink("docs", repo.join("manual")).unwrap();
+        symlink(&outside, repo.join("docs/etc")).unwrap();
+        (base, repo)
+    }
+
+    #[test]
+    fn test_normalize() {
+        let guide = Path::new("docs/guide");
+        assert_eq!(
+            normalize(guide, "../shared/header.html").unwrap(),
+            PathBuf::from("docs/shared/header.html")
+        );
+        assert_eq!(
+            normalize(guide, "./v1..2.txt").unwrap(),
+            PathBuf::from("docs/guide/v1..2.txt")
+        );
+        assert_eq!(
+            normalize(guide, "/src/lib.rs").unwrap(),
+            PathBuf::from("src/lib.rs")
+        );
+        let err = normalize(guide, "../../../etc/passwd").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Path ../../../etc/passwd escapes the repository: too many '..' from docs/guide"
+        );
+        assert!(normalize(guide, "/../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let guide = Some(Path::new("docs/guide"));
+        assert_eq!(
+            candidates("header.html", guide).unwrap(),
+            [
+                PathBuf::from("docs/guide/header.html"),
+                PathBuf::from("header.html")
+            ]
+        );
+        assert_eq!(
+            candidates("/header.html", guide).unwrap(),
+            [PathBuf::from("header.html")]
+        );
+        assert_eq!(
+            candidates("../header.html", guide).unwrap(),
+            [PathBuf::from("docs/header.html")]
+        );
+        assert_eq!(
+            candidates("a/../b.md", None).unwrap(),
+            [PathBuf::from("b.md")]
+        );
+        assert!(candidates("../b.md", None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_within_repo() {
+        let (base, repo) = fixture("within");
+        let sandbox = Sandbox::new(&repo, SymlinkPolicy::WithinRepo);
+        let guide = repo.canonicalize().unwrap().join("docs/guide.md");
+
+        let confine = |path: &str| sandbox.confine(Path::new(path));
+        assert_eq!(confine("docs/guide.md").unwrap(), Some(guide.clone()));
+        assert_eq!(confine("docs/alias.md").unwrap(), Some(guide.clone()));
+        assert_eq!(confine("manual/guide.md").unwrap(), Some(guide));
+        assert_eq!(confine("docs/missing.md").unwrap(), None);
+
+        let err = confine("docs/etc/secret.txt").unwrap_err().to_string();
+        assert!(err.contains("escapes the repository: docs/etc is a symlink to"));
+        let err = confine("manual/etc/secret.txt").unwrap_err().to_string();
+        assert!(err.contains("manual/etc is a symlink"));
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_never_and_always() {
+        let (base, repo) = fixture("policies");
+
+        let never = Sandbox::new(&repo, SymlinkPolicy::Never);
+        assert!(never.confine(Path::new("docs/guide.md")).unwrap().is_some());
+        let err = never
+            .confine(Path::new("manual/guide.md"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("symlink at manual,"));
+
+        let always = Sandbox::new(&repo, SymlinkPolicy::Always);
+        let secret = always
+            .confine(Path::new("docs/etc/secret.txt"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(secret).unwrap(), "secret");
+
+        std::fs::remove_dir_all(base).unwrap();
+    }
+}
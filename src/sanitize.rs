@@ -0,0 +1,316 @@
+//! Allowlist sanitization for transcluded HTML
+//!
+//! Remote servers can return anything, including `<script>` tags and `on*`
+//! handlers. Sanitizing keeps only allowlisted tags and attributes and reports
+//! what was stripped so it can be surfaced as a warning. Markdown links get the
+//! same scheme check as HTML URL attributes.
+
+use anyhow::Result;
+use lol_html::{RewriteStrSettings, element, rewrite_str};
+use regex::{Captures, Regex};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::LazyLock;
+
+use crate::config::SanitizeConfig;
+
+/// Tags whose content is never meaningful outside their element, so they are
+/// removed together with their content rather than unwrapped
+const DROP_WITH_CONTENT: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "template", "noscript", "frame", "frameset",
+    "applet",
+];
+
+/// Attributes that carry URLs and are checked for scriptable schemes
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "action", "formaction", "xlink:href"];
+
+/// Named character references that can spell out or split a URL scheme
+const NAMED_REFERENCES: &[(&str, char)] = &[
+    ("colon", ':'),
+    ("Tab", '\t'),
+    ("NewLine", '\n'),
+    ("amp", '&'),
+    ("sol", '/'),
+    ("period", '.'),
+    ("lpar", '('),
+    ("rpar", ')'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("lt", '<'),
+    ("gt", '>'),
+];
+
+/// Decode character references (`&#106;`, `&#x6A;`, `&colon;`) as a browser would
+/// In attribute values the closing `;` of a numeric reference is optional.
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (c, len) = decode_reference(rest).unwrap_or(('&', 1));
+        decoded.push(c);
+        rest = &rest[len..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The character a reference at the start of `text` stands for, and its length
+fn decode_reference(text: &str) -> Option<(char, usize)> {
+    let body = text.strip_prefix('&')?;
+    if let Some(numeric) = body.strip_prefix('#') {
+        let (digits, radix, prefix) = match numeric.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16, 2),
+            None => (numeric, 10, 1),
+        };
+        let count = digits.chars().take_while(|c| c.is_digit(radix)).count();
+        if count == 0 {
+            return None;
+        }
+        let code = u32::from_str_radix(&digits[..count], radix).unwrap_or(u32::MAX);
+        let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+        let semicolon = usize::from(digits[count..].starts_with(';'));
+        return Some((c, 1 + prefix + count + semicolon));
+    }
+    NAMED_REFERENCES.iter().find_map(|(name, c)| {
+        body.strip_prefix(name)
+            .and_then(|after| after.strip_prefix(';'))
+            .map(|_| (*c, name.len() + 2))
+    })
+}
+
+/// Returns true if the URL uses a scheme that can execute code
+/// The value is judged as the browser reads it: references decoded, then
+/// whitespace and control characters (which browsers skip) removed.
+fn is_dangerous_url(attr: &str, value: &str) -> bool {
+    let normalized: String = decode_entities(value)
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if normalized.starts_with("javascript:") || normalized.starts_with("vbscript:") {
+        return true;
+    }
+    // Inline images are fine; any other data: URL is not
+    normalized.starts_with("data:") && !(attr == "src" && normalized.starts_with("data:image/"))
+}
+
+/// Sanitize HTML against the configured allowlist
+/// Returns the sanitized HTML and a description of everything that was removed
+pub fn sanitize_html(html: &str, config: &SanitizeConfig) -> Result<(String, Vec<String>)> {
+    let removed: Rc<RefCell<BTreeMap<String, usize>>> = Rc::new(RefCell::new(BTreeMap::new()));
+    let removed_clone = removed.clone();
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![element!("*", move |el| {
+            let mut removed = removed_clone.borrow_mut();
+            let tag = el.tag_name().to_lowercase();
+
+            if DROP_WITH_CONTENT.contains(&tag.as_str()) {
+                *removed.entry(format!("<{}> element", tag)).or_default() += 1;
+                el.remove();
+                return Ok(());
+            }
+
+            if !config.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                *removed.entry(format!("<{}> tag", tag)).or_default() += 1;
+                el.remove_and_keep_content();
+                return Ok(());
+            }
+
+            let attributes: Vec<(String, String)> = el
+                .attributes()
+                .iter()
+                .map(|a| (a.name(), a.value()))
+                .collect();
+            for (name, value) in attributes {
+                let lower = name.to_lowercase();
+                let allowed = !lower.starts_with("on")
+                    && config
+                        .attributes
+                        .iter()
+                        .any(|a| a.eq_ignore_ascii_case(&lower));
+                if !allowed {
+                    *removed.entry(format!("{} attribute", lower)).or_default() += 1;
+                    el.remove_attribute(&name);
+                } else if URL_ATTRIBUTES.contains(&lower.as_str())
+                    && is_dangerous_url(&lower, &value)
+                {
+                    *removed
+                        .entry(format!("unsafe URL in {}", lower))
+                        .or_default() += 1;
+                    el.remove_attribute(&name);
+                }
+            }
+
+            Ok(())
+        })],
+        ..RewriteStrSettings::default()
+    };
+
+    let sanitized = rewrite_str(html, settings)?;
+    let removed = Rc::try_unwrap(removed).unwrap().into_inner();
+    let report = removed
+        .into_iter()
+        .map(|(what, count)| format!("{} ({})", what, count))
+        .collect();
+
+    Ok((sanitized, report))
+}
+
+/// Inline links and images: `[text](url "title")`, `![alt](<url>)`
+static MARKDOWN_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(!?)\[((?:[^\[\]]|\[[^\]]*\])*)\]\(\s*(<[^>\n]*>|(?:[^\s()]|\([^\s()]*\))*)[^)\n]*\)"#,
+    )
+    .unwrap()
+});
+
+/// Link reference definitions: `[id]: url`
+static MARKDOWN_DEFINITION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^ {0,3}\[[^\]\n]+\]:[ \t]*(<[^>\n]*>|\S+).*$").unwrap());
+
+/// Autolinks: `<scheme:...>`
+static MARKDOWN_AUTOLINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<([A-Za-z][A-Za-z0-9+.-]{1,31}:[^\s<>]*)>").unwrap());
+
+/// A Markdown link destination as the renderer reads it
+/// Backslash escapes are dropped, which can only make a scheme easier to spot.
+fn is_dangerous_destination(attr: &str, destination: &str) -> bool {
+    let destination = destination.trim_start_matches('<').trim_end_matches('>');
+    is_dangerous_url(attr, &destination.replace('\\', ""))
+}
+
+/// Remove Markdown links, images and definitions whose destination can execute code
+/// Links and images keep their text; definitions are dropped. Returns the
+/// sanitized text and a description of what was removed.
+pub fn sanitize_markdown_links(text: &str) -> (String, Vec<String>) {
+    let mut count = 0;
+    let text = MARKDOWN_LINK.replace_all(text, |caps: &Captures| {
+        let attr = if caps[1].is_empty() { "href" } else { "src" };
+        if is_dangerous_destination(attr, &caps[3]) {
+            count += 1;
+            caps[2].to_string()
+        } else {
+            caps[0].to_string()
+        }
+    });
+    let text = MARKDOWN_DEFINITION.replace_all(&text, |caps: &Captures| {
+        if is_dangerous_destination("href", &caps[1]) {
+            count += 1;
+            String::new()
+        } else {
+            caps[0].to_string()
+        }
+    });
+    let text = MARKDOWN_AUTOLINK.replace_all(&text, |caps: &Captures| {
+        if is_dangerous_url("href", &caps[1]) {
+            count += 1;
+            String::new()
+        } else {
+            caps[0].to_string()
+        }
+    });
+
+    let report = if count > 0 {
+        vec![format!("unsafe URL in Markdown link ({})", count)]
+    } else {
+        Vec::new()
+    };
+    (text.into_owned(), report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strips_scripts_and_handlers() {
+        let html =
+            r#"<p onclick="steal()">Hi<script>alert(1)</script></p><a href="javascript:x()">x</a>"#;
+        let (result, report) = sanitize_html(html, &SanitizeConfig::default()).unwrap();
+        assert_eq!(result, "<p>Hi</p><a>x</a>");
+        assert_eq!(
+            report,
+            vec![
+                "<script> element (1)",
+                "onclick attribute (1)",
+                "unsafe URL in href (1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encoded_schemes() {
+        for href in [
+            "&#106;avascript:alert(1)",
+            "&#0000106avascript:alert(1)",
+            "jav&#x61;script:alert(1)",
+            "jav&#X61script&colon;alert(1)",
+            "java\tscript:alert(1)",
+            "java&#x09;script:alert(1)",
+            "java&Tab;scr&NewLine;ipt:alert(1)",
+            " \u{1}javascript:alert(1)",
+        ] {
+            let html = format!(r#"<a href="{}">x</a>"#, href);
+            let (result, report) = sanitize_html(&html, &SanitizeConfig::default()).unwrap();
+            assert_eq!(result, "<a>x</a>", "{}", href);
+            assert_eq!(report, vec!["unsafe URL in href (1)"]);
+        }
+
+        let (result, _) = sanitize_html(
+            r#"<a href="/a?x=1&amp;y=2">x</a>"#,
+            &SanitizeConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(result, r#"<a href="/a?x=1&amp;y=2">x</a>"#);
+        assert_eq!(decode_entities("a &amp b &#x;"), "a &amp b &#x;");
+    }
+
+    #[test]
+    fn test_markdown_links() {
+        let markdown = "[ok](https://x.org) [bad](javascript:alert(1)) \
+                        [esc](java\\script&#58;x) ![img](data:image/png;base64,AA) \
+                        ![svg](data:text/html,x) <javascript:alert(1)> <https://x.org>\n\
+                        [ref]: vbscript:msgbox\n\
+                        [fine]: /docs/\n";
+        let (result, report) = sanitize_markdown_links(markdown);
+        assert_eq!(
+            result,
+            "[ok](https://x.org) bad esc ![img](data:image/png;base64,AA) \
+             svg  <https://x.org>\n\n[fine]: /docs/\n"
+        );
+        assert_eq!(report, vec!["unsafe URL in Markdown link (5)"]);
+    }
+
+    #[test]
+    fn test_unwraps_unknown_tags() {
+        let html =
+            r#"<form action="/x"><p class="note">Keep <font color="red">me</font></p></form>"#;
+        let (result, report) = sanitize_html(html, &SanitizeConfig::default()).unwrap();
+        assert_eq!(result, r#"<p class="note">Keep me</p>"#);
+        assert_eq!(report, vec!["<font> tag (1)", "<form> tag (1)"]);
+    }
+
+    #[test]
+    fn test_clean_html_is_untouched() {
+        let html = r#"<h2 id="x">Title</h2><img src="data:image/png;base64,AA" alt="a">"#;
+        let (result, report) = sanitize_html(html, &SanitizeConfig::default()).unwrap();
+        assert_eq!(result, html);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn test_custom_allowlist() {
+        let config = SanitizeConfig {
+            tags: vec!["p".to_string(), "input".to_string()],
+            attributes: vec!["type".to_string()],
+            ..SanitizeConfig::default()
+        };
+        let html = r#"<p class="x"><input type="text" name="q"></p>"#;
+        let (result, _) = sanitize_html(html, &config).unwrap();
+        assert_eq!(result, r#"<p><input type="text"></p>"#);
+    }
+}
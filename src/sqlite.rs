@@ -0,0 +1,111 @@
+//! SQLite query sources (`data/errors.sqlite?sql=SELECT code, message FROM errors`)
+//!
+//! Databases are opened read-only and only read-only statements are run, so a
+//! transclusion can never modify the file.
+
+use anyhow::{Context, Result, anyhow};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+
+/// Returns true if the path is a SQLite database
+pub fn is_database(uri: &str) -> bool {
+    uri.ends_with(".sqlite") || uri.ends_with(".sqlite3") || uri.ends_with(".db")
+}
+
+/// Run a query and return its rows, with the column names as the first row
+pub fn query(path: &Path, sql: &str) -> Result<Vec<Vec<String>>> {
+    let connection = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .with_context(|| format!("Failed to open database: {}", path.display()))?;
+    connection
+        .pragma_update(None, "query_only", true)
+        .context("Failed to make the connection read-only")?;
+
+    let mut statement = connection
+        .prepare(sql)
+        .with_context(|| format!("Invalid query: {}", sql))?;
+    if !statement.readonly() {
+        return Err(anyhow!("Only read-only queries are allowed: {}", sql));
+    }
+
+    let header: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let width = header.len();
+    let mut rows = vec![header];
+
+    let mut results = statement.query([])?;
+    while let Some(row) = results.next()? {
+        let cells = (0..width)
+            .map(|i| Ok(cell_text(row.get_ref(i)?)))
+            .collect::<Result<Vec<_>>>()?;
+        rows.push(cells);
+    }
+    Ok(rows)
+}
+
+fn cell_text(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(text) => String::from_utf8_lossy(text).to_string(),
+        ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_database(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("liaison-{}-{}.sqlite", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE errors (code TEXT, message TEXT, retry INTEGER);
+                 INSERT INTO errors VALUES ('E2', 'Timed out', 1), ('E1', 'Bad input', NULL);",
+            )
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_query_rows() {
+        let path = fixture_database("rows");
+        let rows = query(
+            &path,
+            "SELECT code, message, retry FROM errors ORDER BY code",
+        )
+        .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["code", "message", "retry"],
+                vec!["E1", "Bad input", ""],
+                vec!["E2", "Timed out", "1"],
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_query_is_read_only() {
+        let path = fixture_database("readonly");
+        assert!(query(&path, "DELETE FROM errors").is_err());
+        assert!(query(&path, "CREATE TABLE t (x)").is_err());
+        assert_eq!(
+            query(&path, "SELECT count(*) AS n FROM errors").unwrap()[1],
+            vec!["2"]
+        );
+        assert!(query(Path::new("/nonexistent/liaison.sqlite"), "SELECT 1").is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+//! Rendering rows of cells as Markdown, HTML or CSV tables
+//!
+//! The first row is the header. Rows shorter than the widest row are padded
+//! with empty cells.
+
+use anyhow::{Result, anyhow};
+
+use crate::html::escape_html;
+
+/// Output format for tabular sources, chosen with `?format=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Csv,
+}
+
+impl Format {
+    /// Parse a `?format=` value; Markdown when absent
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value {
+            None | Some("md") => Ok(Format::Markdown),
+            Some("html") => Ok(Format::Html),
+            Some("csv") => Ok(Format::Csv),
+            Some(other) => Err(anyhow!(
+                "Unknown format '{}' (expected md, html or csv)",
+                other
+            )),
+        }
+    }
+}
+
+/// Render rows in the given format
+pub fn render(rows: &[Vec<String>], format: Format) -> String {
+    match format {
+        Format::Markdown => to_markdown(rows),
+        Format::Html => to_html(rows),
+        Format::Csv => to_csv(rows),
+    }
+}
+
+fn width(rows: &[Vec<String>]) -> usize {
+    rows.iter().map(|r| r.len()).max().unwrap_or(0)
+}
+
+/// A cell's text on one line; newlines would end a Markdown table row
+fn flatten(cell: &str) -> String {
+    cell.split(['\n', '\r'])
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Escape a cell's text so it stays in its cell and isn't read as HTML
+/// `<` would open a tag and `&` an entity, so both are shown literally.
+fn escape_cell(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if matches!(c, '\\' | '|' | '<' | '&') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A GitHub-flavored Markdown table
+pub fn to_markdown(rows: &[Vec<String>]) -> String {
+    let width = width(rows);
+    if width == 0 {
+        return String::new();
+    }
+
+    let line = |row: &[String]| {
+        let cells: Vec<String> = (0..width)
+            .map(|i| {
+                let cell = row.get(i).map(|c| flatten(c)).unwrap_or_default();
+                escape_cell(&cell)
+            })
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut lines = vec![line(&rows[0])];
+    lines.push(format!("|{}", " --- |".repeat(width)));
+    for row in &rows[1..] {
+        lines.push(line(row));
+    }
+    lines.join("\n")
+}
+
+/// An HTML table with a `<thead>` for the first row
+pub fn to_html(rows: &[Vec<String>]) -> String {
+    let width = width(rows);
+    if width == 0 {
+        return String::new();
+    }
+
+    let line = |row: &[String], tag: &str| {
+        let cells: String = (0..width)
+            .map(|i| {
+                let cell = row.get(i).map(|c| escape_html(c)).unwrap_or_default();
+                format!("<{tag}>{cell}</{tag}>")
+            })
+            .collect();
+        format!("<tr>{}</tr>", cells)
+    };
+
+    let mut lines = vec![
+        "<table>".to_string(),
+        "<thead>".to_string(),
+        line(&rows[0], "th"),
+        "</thead>".to_string(),
+    ];
+    if rows.len() > 1 {
+        lines.push("<tbody>".to_string());
+        for row in &rows[1..] {
+            lines.push(line(row, "td"));
+        }
+        lines.push("</tbody>".to_string());
+    }
+    lines.push("</table>".to_string());
+    lines.join("\n")
+}
+
+/// RFC 4180 CSV; fields with commas, quotes or line breaks are quoted
+pub fn to_csv(rows: &[Vec<String>]) -> String {
+    let width = width(rows);
+    rows.iter()
+        .map(|row| {
+            (0..width)
+                .map(|i| {
+                    let cell = row.get(i).map(String::as_str).unwrap_or_default();
+                    if cell.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", cell.replace('"', "\"\""))
+                    } else {
+                        cell.to_string()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(data: &[&[&str]]) -> Vec<Vec<String>> {
+        data.iter()
+            .map(|r| r.iter().map(|c| c.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_markdown_table() {
+        let table = rows(&[
+            &["Plan", "Price"],
+            &["Pro", "$10 | mo"],
+            &["<b>Team</b>", "&lt;ask&gt;"],
+            &["Free"],
+        ]);
+        assert_eq!(
+            to_markdown(&table),
+            "| Plan | Price |\n| --- | --- |\n| Pro | $10 \\| mo |\n\
+             | \\<b>Team\\</b> | \\&lt;ask\\&gt; |\n| Free |  |"
+        );
+    }
+
+    #[test]
+    fn test_html_table() {
+        let table = rows(&[&["A", "B"], &["<1>", "2"]]);
+        assert_eq!(
+            to_html(&table),
+            "<table>\n<thead>\n<tr><th>A</th><th>B</th></tr>\n</thead>\n<tbody>\n\
+             <tr><td>&lt;1&gt;</td><td>2</td></tr>\n</tbody>\n</table>"
+        );
+    }
+
+    #[test]
+    fn test_csv_table() {
+        let table = rows(&[
+            &["code", "message"],
+            &["E1", "bad \"input\", retry"],
+            &["E2"],
+        ]);
+        assert_eq!(
+            to_csv(&table),
+            "code,message\nE1,\"bad \"\"input\"\", retry\"\nE2,"
+        );
+        assert_eq!(Format::parse(Some("csv")).unwrap(), Format::Csv);
+        assert!(Format::parse(Some("xml")).is_err());
+    }
+}
@@ -0,0 +1,5 @@
+fn main() {
+    // liaison id=usage
+    let client = Client::new();
+    // liaison end
+}
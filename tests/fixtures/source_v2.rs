@@ -0,0 +1,5 @@
+// liaison id=helper
+fn helper() -> i64 {
+    42
+}
+// liaison end
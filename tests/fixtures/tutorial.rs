@@ -0,0 +1,11 @@
+// liaison id=step-1
+let x = 1;
+// liaison end
+
+// liaison id=other
+let y = 2;
+// liaison end
+
+// liaison id=step-2
+let z = x + 1;
+// liaison end
@@ -266,6 +266,699 @@ fn test_recursive_html_to_html_with_comment() {
     );
 }
 
+// =============================================================================
+// Relative link rewriting tests
+// =============================================================================
+
+#[test]
+fn test_relative_links_rebased_to_host() {
+    let temp = TempFile::new(
+        "temp_links.md",
+        r#"# Host
+
+<!-- liaison transclude="docs/guide/intro.md#overview" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let updated = temp.read();
+    assert!(updated.contains("![diagram](docs/guide/img/flow.png)"));
+    assert!(updated.contains("[API reference](docs/api.html)"));
+}
+
+#[test]
+fn test_relative_links_norewrite() {
+    let temp = TempFile::new(
+        "temp_links_norewrite.md",
+        r#"<!-- liaison transclude="docs/guide/intro.md#overview?norewrite" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(output.status.success());
+
+    let updated = temp.read();
+    assert!(updated.contains("![diagram](img/flow.png)"));
+    assert!(updated.contains("[API reference](../api.html)"));
+}
+
+// =============================================================================
+// Heading shift tests
+// =============================================================================
+
+#[test]
+fn test_markdown_heading_shift() {
+    let temp = TempFile::new(
+        "temp_headings.md",
+        r#"## Getting Started
+
+<!-- liaison transclude="tests/fixtures/sections.md#install?heading-base=3" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let updated = temp.read();
+    assert!(updated.contains("\n### Installation\n"));
+    assert!(updated.contains("\n#### From crates.io\n"));
+    // Comments inside fenced code blocks are not headings
+    assert!(updated.contains("\n# install the binary\n"));
+}
+
+#[test]
+fn test_html_heading_shift() {
+    let temp = TempFile::new(
+        "temp_headings.html",
+        r#"<!DOCTYPE html>
+<html>
+<body>
+<section transclude="tests/fixtures/docs/api.html#summary?shift-headings=1"></section>
+</body>
+</html>"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(output.status.success());
+
+    let updated = temp.read();
+    assert!(updated.contains("<h2>API</h2>"));
+    assert!(updated.contains("<h3>Endpoints</h3>"));
+}
+
+// =============================================================================
+// Id scoping tests
+// =============================================================================
+
+#[test]
+fn test_id_prefix_from_host_element() {
+    let temp = TempFile::new(
+        "temp_ids.html",
+        r#"<!DOCTYPE html>
+<html>
+<body>
+<div id="signup" transclude="tests/fixtures/form.html#field?id-prefix"></div>
+<div id="invite" transclude="tests/fixtures/form.html#field?id-prefix"></div>
+</body>
+</html>"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let updated = temp.read();
+    for site in ["signup", "invite"] {
+        assert!(updated.contains(&format!(r#"<label for="{}-email">"#, site)));
+        assert!(updated.contains(&format!(
+            r#"<input id="{site}-email" aria-describedby="{site}-email-help">"#
+        )));
+        assert!(updated.contains(&format!(r#"<small id="{}-email-help">"#, site)));
+    }
+    assert!(!updated.contains(r#"id="email""#));
+}
+
+#[test]
+fn test_id_prefix_explicit() {
+    let temp = TempFile::new(
+        "temp_ids_explicit.html",
+        r#"<!DOCTYPE html>
+<html>
+<body>
+<div transclude="tests/fixtures/form.html#field?id-prefix=footer-"></div>
+</body>
+</html>"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(output.status.success());
+
+    let updated = temp.read();
+    assert!(updated.contains(r#"<label for="footer-email">"#));
+    assert!(updated.contains(r#"<small id="footer-email-help">"#));
+}
+
+// =============================================================================
+// Literal encoding tests
+// =============================================================================
+
+#[test]
+fn test_as_raw_rust_str() {
+    let temp = TempFile::new(
+        "temp_literal.rs",
+        r#"const EXAMPLE: &str =
+// liaison transclude="tests/fixtures/code_source.rs#example?as=raw-rust-str"
+// liaison end
+;
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let updated = temp.read();
+    // The snippet contains `"H`, so one hash is enough to delimit it
+    assert!(updated.contains("r#\"fn main() {"));
+    assert!(updated.contains("println!(\"Hello & goodbye\");\n}\"#\n"));
+}
+
+#[test]
+fn test_as_json_str_with_indent() {
+    let temp = TempFile::new(
+        "temp_literal.py",
+        r#"CONFIG = {
+    "helper": (
+        # liaison transclude="tests/fixtures/source.rs#helper?as=json-str&indent"
+        # liaison end
+    ),
+}
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(output.status.success());
+
+    let updated = temp.read();
+    assert!(updated.contains("        \"fn helper() -> i32 {\\n    42\\n}\"\n"));
+}
+
+// =============================================================================
+// Diff tests
+// =============================================================================
+
+#[test]
+fn test_diff_in_markdown_is_fenced() {
+    let temp = TempFile::new(
+        "temp_diff.md",
+        r#"# Migrating
+<!-- liaison transclude="diff:source.rs#helper..source_v2.rs#helper" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let updated = temp.read();
+    assert!(updated.contains(
+        "```diff\n--- source.rs#helper\n+++ source_v2.rs#helper\n@@ -1,3 +1,3 @@\n\
+         -fn helper() -> i32 {\n+fn helper() -> i64 {\n     42\n }\n```"
+    ));
+}
+
+#[test]
+fn test_diff_in_html_is_annotated() {
+    let temp = TempFile::new(
+        "temp_diff.html",
+        r#"<pre class="diff" transclude="diff:source.rs#helper..source_v2.rs#helper"></pre>
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(output.status.success());
+
+    let updated = temp.read();
+    assert!(updated.contains(r#"<span class="diff-del">-fn helper() -&gt; i32 {</span>"#));
+    assert!(updated.contains(r#"<span class="diff-add">+fn helper() -&gt; i64 {</span>"#));
+    assert!(updated.contains(r#"<span class="diff-context">     42</span>"#));
+}
+
+// =============================================================================
+// Metadata tests
+// =============================================================================
+
+#[test]
+fn test_inline_metadata_in_sentence() {
+    let temp = TempFile::new(
+        "temp_inline.md",
+        r#"Add `<!-- liaison transclude="cargo:package.name" -->old<!-- liaison end --> = "<!-- liaison transclude="cargo:package.version" --><!-- liaison end -->"` to Cargo.toml.
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let version = env!("CARGO_PKG_VERSION");
+    assert_eq!(
+        temp.read(),
+        format!(
+            "Add `<!-- liaison transclude=\"cargo:package.name\" -->liaison<!-- liaison end --> = \"<!-- liaison transclude=\"cargo:package.version\" -->{}<!-- liaison end -->\"` to Cargo.toml.\n",
+            version
+        )
+    );
+}
+
+#[test]
+fn test_inline_rejects_multiline_content() {
+    let temp = TempFile::new(
+        "temp_inline_multi.md",
+        r#"See <!-- liaison transclude="source.rs#helper" --><!-- liaison end --> here.
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("single line"));
+}
+
+#[test]
+fn test_inline_inside_id_block() {
+    let temp = TempFile::new(
+        "temp_inline_id.txt",
+        r#"<!-- liaison id=install -->
+Version <!-- liaison transclude="cargo:package.version" -->old<!-- liaison end -->.
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(temp.read().contains(&format!(
+        "-->{}<!-- liaison end -->.",
+        env!("CARGO_PKG_VERSION")
+    )));
+
+    let output = temp.run_liaison_with_args(&["--reset"]);
+    assert!(output.status.success());
+    assert!(temp.read().contains(
+        "Version <!-- liaison transclude=\"cargo:package.version\" --><!-- liaison end -->."
+    ));
+}
+
+// =============================================================================
+// Multi-item tests
+// =============================================================================
+
+#[test]
+fn test_glob_reference_tracks_each_file() {
+    let temp = TempFile::new(
+        "temp_glob.md",
+        r#"## Examples
+<!-- liaison transclude="examples/*.rs#usage?header=### {name}" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let updated = temp.read();
+    assert!(updated.contains(
+        "### advanced.rs\nlet client = Client::builder().retries(3).build();\n\n\
+         ### basic.rs\nlet client = Client::new();\n<!-- liaison end -->"
+    ));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("examples/advanced.rs"));
+    assert!(stderr.contains("examples/basic.rs"));
+}
+
+#[test]
+fn test_glob_items_keep_their_own_links() {
+    let dir = scratch_dir("glob-links");
+    for name in ["a", "b"] {
+        fs::create_dir_all(dir.join("docs").join(name)).unwrap();
+        fs::write(
+            dir.join("docs").join(name).join("intro.md"),
+            format!("Intro {}: ![diagram](pic.png)\n", name),
+        )
+        .unwrap();
+    }
+    let host = dir.join("README.md");
+    fs::write(
+        &host,
+        "<!-- liaison transclude=\"docs/*/intro.md\" -->\n<!-- liaison end -->\n",
+    )
+    .unwrap();
+
+    let output = Command::new(liaison_bin())
+        .arg("README.md")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let result = fs::read_to_string(&host).unwrap();
+    assert!(
+        result.contains(
+            "Intro a: ![diagram](docs/a/pic.png)\n\n\
+             Intro b: ![diagram](docs/b/pic.png)\n<!-- liaison end -->"
+        ),
+        "{}",
+        result
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+// =============================================================================
+// Database tests
+// =============================================================================
+
+/// A database of error codes; the file is removed when the guard drops
+fn errors_database(name: &str) -> TempFile {
+    let database = TempFile::new(name, "");
+    let connection = rusqlite::Connection::open(database.path()).unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE errors (code TEXT, message TEXT);
+             INSERT INTO errors VALUES ('E2', 'Timed out'), ('E1', 'Bad <input>');",
+        )
+        .unwrap();
+    database
+}
+
+#[test]
+fn test_database_query_renders_for_host() {
+    let _database = errors_database("temp_errors.sqlite");
+    let markdown = TempFile::new(
+        "temp_errors.md",
+        r#"<!-- liaison transclude="temp_errors.sqlite?sql=SELECT code, message FROM errors ORDER BY code" -->
+<!-- liaison end -->
+"#,
+    );
+    let html = TempFile::new(
+        "temp_errors.html",
+        r#"<div transclude="temp_errors.sqlite?sql=SELECT code FROM errors WHERE code = 'E1'"></div>
+"#,
+    );
+
+    for temp in [&markdown, &html] {
+        let output = temp.run_liaison();
+        assert!(
+            output.status.success(),
+            "liaison failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    assert!(
+        markdown.read().contains(
+            "| code | message |\n| --- | --- |\n| E1 | Bad \\<input> |\n| E2 | Timed out |"
+        )
+    );
+    assert!(html.read().contains("<tr><th>code</th></tr>"));
+    assert!(html.read().contains("<tr><td>E1</td></tr>"));
+}
+
+#[test]
+fn test_database_rejects_writes() {
+    let database = errors_database("temp_writes.sqlite");
+    let temp = TempFile::new(
+        "temp_writes.md",
+        r#"<!-- liaison transclude="temp_writes.sqlite?sql=DELETE FROM errors" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("read-only"));
+
+    let connection = rusqlite::Connection::open(database.path()).unwrap();
+    let count: i64 = connection
+        .query_row("SELECT count(*) FROM errors", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+// =============================================================================
+// Integrity tests
+// =============================================================================
+
+#[test]
+fn test_pin_fills_in_sha256() {
+    let temp = TempFile::new(
+        "temp_pin.md",
+        r#"<!-- liaison transclude="source.rs#helper?sha256=" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = Command::new(liaison_bin())
+        .args(["pin", temp.path()])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "liaison pin failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let pinned = temp.read();
+    let digest = pinned
+        .split("sha256=")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    assert_eq!(digest.len(), 64);
+
+    // The pin matches, so a normal run succeeds
+    let output = temp.run_liaison();
+    assert!(output.status.success());
+    assert!(temp.read().contains("fn helper() -> i32"));
+}
+
+#[test]
+fn test_pin_leaves_prose_alone() {
+    let temp = TempFile::new(
+        "temp_pin_prose.md",
+        r#"Pin with `transclude="source.rs#helper?sha256="`, then run `liaison pin`.
+<!-- liaison transclude="source.rs#helper?sha256=" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = Command::new(liaison_bin())
+        .args(["pin", temp.path()])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "liaison pin failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let pinned = temp.read();
+    assert!(pinned.starts_with("Pin with `transclude=\"source.rs#helper?sha256=\"`"));
+    assert_eq!(pinned.matches("sha256=\"").count(), 1);
+}
+
+#[test]
+fn test_integrity_mismatch_fails_despite_ignore_errors() {
+    let temp = TempFile::new(
+        "temp_integrity.md",
+        r#"<!-- liaison transclude="source.rs#helper?sha256=0000" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison_with_args(&["--ignore-errors"]);
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Integrity mismatch for source.rs: expected sha256 0000, got "));
+    assert!(!temp.read().contains("fn helper()"));
+}
+
+// =============================================================================
+// Path resolution tests
+// =============================================================================
+
+#[test]
+fn test_parent_and_root_anchored_paths() {
+    let temp = TempFile::new(
+        "temp_paths.md",
+        r#"<!-- liaison transclude="../fixtures/source.rs#helper" -->
+<!-- liaison end -->
+<!-- liaison transclude="/tests/fixtures/source.rs#helper" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(temp.read().matches("fn helper() -> i32").count(), 2);
+}
+
+#[test]
+fn test_parent_path_cannot_leave_repo() {
+    let temp = TempFile::new(
+        "temp_escape.md",
+        r#"<!-- liaison transclude="../../../etc/passwd" -->
+<!-- liaison end -->
+"#,
+    );
+
+    let output = temp.run_liaison();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("escapes the repository: too many '..' from tests/fixtures"),
+        "{}",
+        stderr
+    );
+}
+
+// =============================================================================
+// Remote content tests
+// =============================================================================
+
+/// Serve `body` as `content_type` to every request, for as long as the test runs
+fn serve_forever(content_type: &'static str, body: &'static str) -> String {
+    use std::io::{BufRead, BufReader, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 && line != "\r\n" {
+                line.clear();
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+    base
+}
+
+/// A scratch directory outside the repository, so caches and lockfiles land there
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("liaison-it-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_remote_plain_text_is_sanitized_in_markup_hosts() {
+    let base = serve_forever(
+        "text/plain",
+        "<script>steal()</script><img src=\"x.png\" onerror=\"steal()\"> [x](javascript:alert(1))\n",
+    );
+    let dir = scratch_dir("plain-text");
+    let host = dir.join("host.md");
+    fs::write(
+        &host,
+        format!(
+            "<!-- liaison transclude=\"{}/notes.txt\" -->\n<!-- liaison end -->\n",
+            base
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(liaison_bin())
+        .arg("host.md")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let result = fs::read_to_string(&host).unwrap();
+    assert!(result.contains("<img src=\"x.png\"> x\n"), "{}", result);
+    assert!(!result.contains("steal()"));
+    assert!(!result.contains("javascript:"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_nested_policy_counts_only_real_markers() {
+    let prose = serve_forever("text/markdown", "Set transclude=\"x.md\" on any element.\n");
+    let marker = serve_forever(
+        "text/markdown",
+        "<!-- liaison transclude=\"x.md\" -->\n<!-- liaison end -->\n",
+    );
+    let dir = scratch_dir("nested-policy");
+    fs::write(dir.join(".liaison.toml"), "[network]\nnested = false\n").unwrap();
+    let run = |base: &str| {
+        fs::write(
+            dir.join("host.md"),
+            format!(
+                "<!-- liaison transclude=\"{}/doc.md\" -->\n<!-- liaison end -->\n",
+                base
+            ),
+        )
+        .unwrap();
+        Command::new(liaison_bin())
+            .arg("host.md")
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&prose);
+    assert!(
+        output.status.success(),
+        "liaison failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let result = fs::read_to_string(dir.join("host.md")).unwrap();
+    assert!(result.contains("Set transclude=\"x.md\" on any element."));
+
+    let output = run(&marker);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("network.nested = false"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
 // =============================================================================
 // Indentation tests
 // =============================================================================